@@ -0,0 +1,290 @@
+//! 通話中のメンバーの実績の進み具合を比べる一覧
+
+use std::{collections::HashSet, fmt, str::FromStr, time::Duration};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serenity::{
+    builder::{CreateComponents, CreateEmbed},
+    http::Http,
+    model::{
+        application::interaction::{
+            message_component::MessageComponentInteraction, InteractionResponseType,
+        },
+        id::{InteractionId, UserId},
+        Timestamp,
+    },
+};
+
+use crate::{
+    common_games::{store_url, Member},
+    steam::{AchievementSchema, Game},
+    storage::{LoadError, Record, Storage},
+};
+
+/// 1 ページに表示する実績の数
+const PAGE_SIZE: usize = 15;
+
+/// メンバーひとりの実績の解除状況
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Progress {
+    pub member: Member,
+    /// 解除した実績の API の名前
+    ///
+    /// プロフィールが非公開などで読めなかったときは `None`
+    pub unlocked: Option<HashSet<String>>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AchievementsStore {
+    game: Game,
+    achievements: Vec<AchievementSchema>,
+    progress: Vec<Progress>,
+    /// 一覧を作ったユーザー
+    /// このユーザーだけがページを送ることができる
+    owner: UserId,
+    /// 一覧を作った時刻
+    created_at: Timestamp,
+}
+
+impl AchievementsStore {
+    /// 保存するときのキーの接頭辞
+    pub const PERSIST_KEY_PREFIX: &'static str = "achievements-";
+
+    pub fn new(
+        owner: UserId,
+        game: Game,
+        achievements: Vec<AchievementSchema>,
+        progress: Vec<Progress>,
+    ) -> AchievementsStore {
+        AchievementsStore {
+            game,
+            achievements,
+            progress,
+            owner,
+            created_at: Timestamp::now(),
+        }
+    }
+
+    /// 読めたメンバーの誰も解除していない実績
+    fn unclaimed(&self) -> Vec<&AchievementSchema> {
+        self.achievements
+            .iter()
+            .filter(|achievement| {
+                self.progress
+                    .iter()
+                    .filter_map(|progress| progress.unlocked.as_ref())
+                    .all(|unlocked| !unlocked.contains(&achievement.name))
+            })
+            .collect()
+    }
+
+    /// ページの数
+    /// 誰も解除していない実績がないときも空のページを 1 つ表示するので 1 になる
+    fn page_count(&self) -> usize {
+        self.unclaimed().len().div_ceil(PAGE_SIZE).max(1)
+    }
+
+    pub fn load(key: &str, storage: &Storage) -> Result<AchievementsStore, LoadError> {
+        storage.load(key)
+    }
+
+    pub fn save(&self, key: &str, storage: &Storage) -> Result<()> {
+        storage.save(key, self)
+    }
+
+    /// 一覧を作ったコマンドのインタラクションの ID から保存するキーを作る
+    pub fn generate_persist_key(interaction_id: InteractionId) -> String {
+        format!("{}{interaction_id}", Self::PERSIST_KEY_PREFIX)
+    }
+
+    /// 作られてから `ttl` 以上経っているか
+    pub fn is_expired(&self, ttl: Duration) -> bool {
+        let elapsed = Timestamp::now().unix_timestamp() - self.created_at.unix_timestamp();
+        elapsed >= ttl.as_secs() as i64
+    }
+
+    /// 期限切れと読めなくなった一覧を削除して、削除した数を返す
+    pub fn collect_garbage(storage: &Storage, ttl: Duration) -> Result<usize> {
+        let mut removed = 0;
+        for key in storage.keys(Self::PERSIST_KEY_PREFIX)? {
+            let expired = match Self::load(&key, storage) {
+                Ok(store) => store.is_expired(ttl),
+                Err(LoadError::Missing) => false,
+                Err(LoadError::Corrupt(_)) => true,
+            };
+            if expired {
+                storage.remove(&key)?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+}
+
+impl Record for AchievementsStore {
+    const VERSION: u32 = 1;
+}
+
+/// ページを送るボタンに設定するカスタムID
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AchievementsCustomId {
+    /// ストアのキー
+    pub key: String,
+    /// 移動する先のページ
+    pub page: usize,
+    /// 同じメッセージ内でカスタムIDが重複しないように、どちらのボタンかも持たせる
+    pub next: bool,
+}
+
+impl FromStr for AchievementsCustomId {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let id = serde_json::from_str(s)?;
+        Ok(id)
+    }
+}
+
+impl fmt::Display for AchievementsCustomId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&serde_json::to_string(&self).expect("convert id to string error"))
+    }
+}
+
+/// ページを送るボタンが押されたときに、元のメッセージを指定されたページに書き換える
+pub async fn handle_component(
+    ctx: impl AsRef<Http>,
+    component: &MessageComponentInteraction,
+    custom_id: AchievementsCustomId,
+    storage: &Storage,
+    ttl: Duration,
+) -> Result<()> {
+    let notice = match AchievementsStore::load(&custom_id.key, storage) {
+        Ok(store) if store.is_expired(ttl) => Some(EXPIRED_NOTICE),
+        Ok(store) if store.owner != component.user.id => {
+            Some("この一覧を操作できるのは `/achievements` を実行した人だけです。")
+        }
+        Ok(store) => {
+            let (embed, components) = create_page(&custom_id.key, &store, custom_id.page);
+            component
+                .create_interaction_response(&ctx, |response| {
+                    response
+                        .kind(InteractionResponseType::UpdateMessage)
+                        .interaction_response_data(|msg| {
+                            msg.set_embed(embed).set_components(components)
+                        })
+                })
+                .await?;
+            None
+        }
+        Err(_) => Some(EXPIRED_NOTICE),
+    };
+    if let Some(notice) = notice {
+        component
+            .create_interaction_response(ctx, |response| {
+                response
+                    .kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|msg| msg.ephemeral(true).content(notice))
+            })
+            .await?;
+    }
+    Ok(())
+}
+
+const EXPIRED_NOTICE: &str =
+    "この一覧は有効期限が切れました。もう一度 `/achievements` を実行してください。";
+
+/// `page` のページを表示する埋め込みとボタンを作る
+///
+/// メンバーごとの進み具合はどのページにも載せ、誰も解除していない実績をページに分ける
+pub fn create_page(
+    key: &str,
+    store: &AchievementsStore,
+    page: usize,
+) -> (CreateEmbed, CreateComponents) {
+    let page_count = store.page_count();
+    let page = page.min(page_count - 1);
+    let unclaimed = store.unclaimed();
+    let total = store.achievements.len();
+
+    let mut progress = store
+        .progress
+        .iter()
+        .map(|progress| match &progress.unlocked {
+            Some(unlocked) => {
+                let count = store
+                    .achievements
+                    .iter()
+                    .filter(|achievement| unlocked.contains(&achievement.name))
+                    .count();
+                format!(
+                    "{} — {count}/{total} ({}%)",
+                    progress.member.name,
+                    count * 100 / total.max(1)
+                )
+            }
+            None => format!("{} — 読めませんでした", progress.member.name),
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    if progress.is_empty() {
+        progress = "Steam IDを登録しているメンバーがいません。".to_string();
+    }
+    let description = if unclaimed.is_empty() {
+        "誰も解除していない実績はありません。".to_string()
+    } else {
+        unclaimed
+            .iter()
+            .skip(page * PAGE_SIZE)
+            .take(PAGE_SIZE)
+            .map(|achievement| {
+                if achievement.description.is_empty() {
+                    format!("- **{}**", achievement.display_name)
+                } else {
+                    format!(
+                        "- **{}** — {}",
+                        achievement.display_name, achievement.description
+                    )
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+
+    let mut embed = CreateEmbed::default();
+    embed
+        .title(format!("{} の実績", store.game.name))
+        .url(store_url(store.game.appid))
+        .description(description)
+        .field("解除した実績", progress, false)
+        .footer(|f| {
+            f.text(format!(
+                "誰も解除していない実績{}件・{}/{page_count}ページ・非公開のプロフィールは読めません",
+                unclaimed.len(),
+                page + 1
+            ))
+        });
+
+    let mut components = CreateComponents::default();
+    components.create_action_row(|r| {
+        for (next, label, target) in [
+            (false, "PREV", page.checked_sub(1)),
+            (true, "NEXT", Some(page + 1).filter(|p| *p < page_count)),
+        ] {
+            // 移動先がないボタンも今のページで作って無効にしておく
+            let custom_id = AchievementsCustomId {
+                key: key.to_string(),
+                page: target.unwrap_or(page),
+                next,
+            };
+            r.create_button(|b| {
+                b.custom_id(custom_id)
+                    .label(label)
+                    .disabled(target.is_none())
+            });
+        }
+        r
+    });
+
+    (embed, components)
+}
@@ -0,0 +1,472 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serenity::model::Timestamp;
+
+use crate::{
+    common_games::AppId,
+    steam::{Game, SteamApiClient},
+    storage::{LoadError, Record, Storage},
+};
+
+/// 保存しておくアプリの一覧
+///
+/// 起動するたびに取得しなくて済むように保存しておく
+#[derive(Serialize, Deserialize, Debug)]
+struct AppList {
+    apps: Vec<Game>,
+    fetched_at: Timestamp,
+}
+
+impl AppList {
+    const PERSIST_KEY: &'static str = "app-list";
+}
+
+impl Record for AppList {
+    const VERSION: u32 = 1;
+}
+
+/// ストアの検索で見つけた日本語の名前
+///
+/// `GetAppList` は英語の名前しか返さないので、見つけたものを貯めておく
+#[derive(Serialize, Deserialize, Default, Debug)]
+struct LocalizedNames {
+    names: HashMap<AppId, String>,
+}
+
+impl LocalizedNames {
+    const PERSIST_KEY: &'static str = "app-localized-names";
+}
+
+impl Record for LocalizedNames {
+    const VERSION: u32 = 1;
+}
+
+/// 飛び飛びに一致するものを探すときに比べる名前の数の上限
+const MAX_FUZZY_CANDIDATES: usize = 20_000;
+
+struct Entry {
+    appid: AppId,
+    name: String,
+    /// [`normalize`] した名前
+    normalized: String,
+}
+
+/// 名前から探すための索引
+///
+/// 入力のたびにすべての名前と比べなくて済むように、文字と 2 文字の並びごとに名前の位置を引けるようにしておく
+#[derive(Default)]
+struct Index {
+    /// 同じアプリの英語と日本語の名前は別々に入れる
+    entries: Vec<Entry>,
+    /// 英語の名前の位置
+    by_appid: HashMap<AppId, usize>,
+    /// 正規化した名前の順に並べた位置
+    ///
+    /// 1 文字だけ入力されたときに前方一致で探す
+    sorted: Vec<u32>,
+    /// 2 文字の並びごとの、それを名前に含む位置 (昇順)
+    bigrams: HashMap<(char, char), Vec<u32>>,
+    /// 文字ごとの、それを名前に含む位置 (昇順)
+    chars: HashMap<char, Vec<u32>>,
+    localized: HashMap<AppId, String>,
+    fetched_at: Option<Timestamp>,
+}
+
+impl Index {
+    fn new(apps: Vec<Game>, localized: HashMap<AppId, String>, fetched_at: Timestamp) -> Index {
+        let mut index = Index {
+            fetched_at: Some(fetched_at),
+            ..Default::default()
+        };
+        for app in apps {
+            if let Some(i) = index.push(app.appid, app.name) {
+                index.by_appid.insert(app.appid, i);
+            }
+        }
+        for (appid, name) in &localized {
+            index.push(*appid, name.clone());
+        }
+        index.localized = localized;
+        let entries = &index.entries;
+        index.sorted.sort_by(|a, b| {
+            entries[*a as usize]
+                .normalized
+                .cmp(&entries[*b as usize].normalized)
+        });
+        index
+    }
+
+    /// 名前を加えて位置を返す
+    ///
+    /// `sorted` は並べなおさないので、続けて加えたあとに並べなおす
+    fn push(&mut self, appid: AppId, name: String) -> Option<usize> {
+        let normalized = normalize(&name);
+        if normalized.is_empty() {
+            return None;
+        }
+        let i = self.entries.len();
+        let chars = normalized.chars().collect::<Vec<_>>();
+        let mut bigrams = chars.windows(2).map(|w| (w[0], w[1])).collect::<Vec<_>>();
+        bigrams.sort();
+        bigrams.dedup();
+        for bigram in bigrams {
+            self.bigrams.entry(bigram).or_default().push(i as u32);
+        }
+        let mut chars = chars;
+        chars.sort();
+        chars.dedup();
+        for c in chars {
+            self.chars.entry(c).or_default().push(i as u32);
+        }
+        self.sorted.push(i as u32);
+        self.entries.push(Entry {
+            appid,
+            name,
+            normalized,
+        });
+        Some(i)
+    }
+
+    /// 日本語の名前を加える。新しく加えたら `true` を返す
+    fn add_localized(&mut self, appid: AppId, name: &str) -> bool {
+        let normalized = normalize(name);
+        let known = self
+            .by_appid
+            .get(&appid)
+            .is_some_and(|i| self.entries[*i].normalized == normalized)
+            || self
+                .localized
+                .get(&appid)
+                .is_some_and(|known| normalize(known) == normalized);
+        if known || self.push(appid, name.to_string()).is_none() {
+            return false;
+        }
+        self.localized.insert(appid, name.to_string());
+        // 1 件だけなので、並べなおさずに入る位置に差し込む
+        let i = self.sorted.pop().unwrap_or_default();
+        let at = self
+            .sorted
+            .partition_point(|j| self.entries[*j as usize].normalized < normalized);
+        self.sorted.insert(at, i);
+        true
+    }
+
+    /// `query` を名前に含むかもしれない位置
+    fn candidates(&self, query: &[char]) -> Vec<u32> {
+        if let [c] = *query {
+            // 1 文字だけのときは前方一致だけを探す
+            let prefix = c.to_string();
+            let start = self
+                .sorted
+                .partition_point(|i| self.entries[*i as usize].normalized < prefix);
+            return self.sorted[start..]
+                .iter()
+                .take_while(|i| self.entries[**i as usize].normalized.starts_with(c))
+                .copied()
+                .collect();
+        }
+        let lists = query
+            .windows(2)
+            .map(|w| self.bigrams.get(&(w[0], w[1])))
+            .collect::<Option<Vec<_>>>()
+            .unwrap_or_default();
+        intersect(lists)
+    }
+
+    /// `query` のすべての文字を名前に含む位置
+    fn fuzzy_candidates(&self, query: &[char]) -> Vec<u32> {
+        let mut chars = query.to_vec();
+        chars.sort();
+        chars.dedup();
+        let lists = chars
+            .iter()
+            .map(|c| self.chars.get(c))
+            .collect::<Option<Vec<_>>>()
+            .unwrap_or_default();
+        intersect(lists)
+    }
+}
+
+/// 昇順に並んだ位置の共通部分
+fn intersect(mut lists: Vec<&Vec<u32>>) -> Vec<u32> {
+    lists.sort_by_key(|list| list.len());
+    let Some((first, rest)) = lists.split_first() else {
+        return Vec::new();
+    };
+    first
+        .iter()
+        .filter(|i| rest.iter().all(|list| list.binary_search(i).is_ok()))
+        .copied()
+        .collect()
+}
+
+/// Steam のすべてのアプリの名前から探すための索引
+///
+/// 英語の名前は `GetAppList` から、日本語の名前はストアの検索の結果から集める。
+/// クローンしたものは同じ中身を共有する
+#[derive(Clone, Default)]
+pub struct AppIndex {
+    index: Arc<RwLock<Index>>,
+}
+
+impl AppIndex {
+    pub fn new() -> AppIndex {
+        AppIndex::default()
+    }
+
+    /// 保存してある一覧を読み込み、古ければ取得しなおす
+    pub async fn refresh(
+        &self,
+        steam: &SteamApiClient,
+        storage: &Storage,
+        max_age: Duration,
+    ) -> Result<()> {
+        if self.fetched_at().is_none() {
+            match storage.load::<AppList>(AppList::PERSIST_KEY) {
+                Ok(list) => self.replace(list, storage),
+                Err(LoadError::Missing) => {}
+                Err(e) => tracing::warn!("{e:?}"),
+            }
+        }
+
+        let fresh = self.fetched_at().is_some_and(|fetched_at| {
+            Timestamp::now().unix_timestamp() - fetched_at.unix_timestamp()
+                < max_age.as_secs() as i64
+        });
+        if fresh {
+            return Ok(());
+        }
+
+        let list = AppList {
+            apps: steam.get_app_list().await?,
+            fetched_at: Timestamp::now(),
+        };
+        storage.save(AppList::PERSIST_KEY, &list)?;
+        tracing::info!("Fetched {} apps", list.apps.len());
+        self.replace(list, storage);
+        Ok(())
+    }
+
+    /// アプリの一覧を読み込んだか
+    pub fn is_loaded(&self) -> bool {
+        self.fetched_at().is_some()
+    }
+
+    fn fetched_at(&self) -> Option<Timestamp> {
+        self.index.read().unwrap().fetched_at
+    }
+
+    /// 索引を作りなおす。日本語の名前は引き継ぐ
+    fn replace(&self, list: AppList, storage: &Storage) {
+        let localized = if self.fetched_at().is_some() {
+            self.index.read().unwrap().localized.clone()
+        } else {
+            match storage.load::<LocalizedNames>(LocalizedNames::PERSIST_KEY) {
+                Ok(localized) => localized.names,
+                Err(LoadError::Missing) => HashMap::new(),
+                Err(e) => {
+                    tracing::warn!("{e:?}");
+                    HashMap::new()
+                }
+            }
+        };
+        let index = Index::new(list.apps, localized, list.fetched_at);
+        *self.index.write().unwrap() = index;
+    }
+
+    /// ストアの検索で見つけた名前を加える
+    ///
+    /// 英語の名前と違うものだけを日本語の名前として覚えて保存する
+    pub fn add_localized(&self, apps: &[Game], storage: &Storage) -> Result<()> {
+        let names = {
+            let mut index = self.index.write().unwrap();
+            let mut added = false;
+            for app in apps {
+                added |= index.add_localized(app.appid, &app.name);
+            }
+            if !added {
+                return Ok(());
+            }
+            index.localized.clone()
+        };
+        storage.save(LocalizedNames::PERSIST_KEY, &LocalizedNames { names })
+    }
+
+    pub fn name(&self, appid: AppId) -> Option<String> {
+        let index = self.index.read().unwrap();
+        let i = *index.by_appid.get(&appid)?;
+        Some(index.entries[i].name.clone())
+    }
+
+    /// 名前が `query` に近いものから `limit` 件まで返す
+    ///
+    /// 完全一致、前方一致、部分一致、飛び飛びに一致の順に並べ、同じ順位なら短い名前を先にする。
+    /// 1 文字だけのときは前方一致だけを探す
+    pub fn search(&self, query: &str, limit: usize) -> Vec<(AppId, String)> {
+        let query = normalize(query);
+        let chars = query.chars().collect::<Vec<_>>();
+        if chars.is_empty() {
+            return Vec::new();
+        }
+
+        let index = self.index.read().unwrap();
+        let mut candidates = index.candidates(&chars);
+        let mut hits = candidates
+            .iter()
+            .filter_map(|i| {
+                let entry = &index.entries[*i as usize];
+                Some((score(&query, &entry.normalized)?, entry))
+            })
+            .collect::<Vec<_>>();
+        if hits.len() < limit && chars.len() > 1 {
+            candidates.sort();
+            hits.extend(
+                index
+                    .fuzzy_candidates(&chars)
+                    .into_iter()
+                    .filter(|i| candidates.binary_search(i).is_err())
+                    .take(MAX_FUZZY_CANDIDATES)
+                    .filter_map(|i| {
+                        let entry = &index.entries[i as usize];
+                        Some((score(&query, &entry.normalized)?, entry))
+                    }),
+            );
+        }
+        hits.sort_by(|(a_score, a), (b_score, b)| {
+            a_score
+                .cmp(b_score)
+                .then(a.normalized.len().cmp(&b.normalized.len()))
+                .then(a.appid.cmp(&b.appid))
+        });
+        // 英語と日本語の両方の名前が当たったときは近いほうだけを残す
+        let mut seen = HashSet::new();
+        hits.into_iter()
+            .filter(|(_, entry)| seen.insert(entry.appid))
+            .take(limit)
+            .map(|(_, entry)| (entry.appid, entry.name.clone()))
+            .collect()
+    }
+}
+
+/// 小さいほど近い
+fn score(query: &str, name: &str) -> Option<usize> {
+    if name == query {
+        return Some(0);
+    }
+    if name.starts_with(query) {
+        return Some(1);
+    }
+    if name.contains(query) {
+        return Some(2);
+    }
+
+    // 飛び飛びに一致するときは、間に挟まった文字が少ないほど近い
+    // 最初に一致した文字より前は数えない
+    let mut chars = name.chars();
+    let mut gaps = 0;
+    let mut started = false;
+    for q in query.chars() {
+        loop {
+            let c = chars.next()?;
+            if c == q {
+                break;
+            }
+            if started {
+                gaps += 1;
+            }
+        }
+        started = true;
+    }
+    Some(3 + gaps)
+}
+
+/// 検索のために名前の表記ゆれをそろえる
+///
+/// - 全角英数字を半角に、ひらがなをカタカナにする
+/// - 大文字を小文字にする
+/// - 空白や記号を取り除く
+pub fn normalize(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+            '\u{3041}'..='\u{3096}' => char::from_u32(c as u32 + 0x60).unwrap_or(c),
+            _ => c,
+        })
+        .flat_map(char::to_lowercase)
+        .filter(|c| c.is_alphanumeric())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index(names: &[(AppId, &str)]) -> AppIndex {
+        let apps = names
+            .iter()
+            .map(|(appid, name)| Game {
+                appid: *appid,
+                name: name.to_string(),
+            })
+            .collect();
+        let index = AppIndex::new();
+        *index.index.write().unwrap() = Index::new(apps, HashMap::new(), Timestamp::now());
+        index
+    }
+
+    #[test]
+    fn search_orders_by_match_kind() {
+        let index = index(&[
+            (1, "Portal 2"),
+            (2, "Portal"),
+            (3, "Aperture Portal Tools"),
+            (4, "Apex Legends"),
+        ]);
+        let appids = index
+            .search("portal", 10)
+            .into_iter()
+            .map(|(appid, _)| appid)
+            .collect::<Vec<_>>();
+        assert_eq!(appids, vec![2, 1, 3]);
+        assert_eq!(index.search("ptl", 10).len(), 3);
+    }
+
+    #[test]
+    fn single_char_matches_prefix_only() {
+        let index = index(&[(1, "Portal"), (2, "Apex"), (3, "Paladins")]);
+        let appids = index
+            .search("p", 10)
+            .into_iter()
+            .map(|(appid, _)| appid)
+            .collect::<Vec<_>>();
+        assert_eq!(appids, vec![1, 3]);
+    }
+
+    #[test]
+    fn localized_names_are_searchable_once() {
+        let index = index(&[(1, "Monster Hunter: World")]);
+        index
+            .index
+            .write()
+            .unwrap()
+            .add_localized(1, "モンスターハンター：ワールド");
+        assert!(!index
+            .index
+            .write()
+            .unwrap()
+            .add_localized(1, "Monster Hunter: World"));
+
+        assert_eq!(
+            index.search("もんすたー", 10),
+            vec![(1, "モンスターハンター：ワールド".to_string())]
+        );
+        assert_eq!(index.search("monster", 10).len(), 1);
+        assert_eq!(index.name(1), Some("Monster Hunter: World".to_string()));
+    }
+}
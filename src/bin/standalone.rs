@@ -0,0 +1,60 @@
+//! shuttle を使わずに動かすためのエントリポイント
+//!
+//! `DISCORD_TOKEN` と `STEAM_API_KEY` を環境変数か TOML の設定ファイルから読む。
+//!
+//! ```sh
+//! cargo run --bin standalone --no-default-features -- Config.toml
+//! ```
+//!
+//! shuttle-persist に保存されていたデータは `migrate` で移行できる。
+//!
+//! ```sh
+//! cargo run --bin standalone --no-default-features -- migrate shuttle_persist/<service> Config.toml
+//! ```
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+use steam_discord_bridge_bot::{
+    config::Config,
+    create_client,
+    migrate::{migrate, OnConflict},
+    storage::LocalStorage,
+};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    tracing_subscriber::fmt::init();
+
+    let mut args = std::env::args().skip(1).collect::<Vec<_>>();
+    if args.first().map(String::as_str) == Some("migrate") {
+        args.remove(0);
+        let on_conflict = if let Some(idx) = args.iter().position(|arg| arg == "--overwrite") {
+            args.remove(idx);
+            OnConflict::Overwrite
+        } else {
+            OnConflict::Skip
+        };
+        let Some(source) = args.first().map(PathBuf::from) else {
+            bail!("usage: standalone migrate <shuttle_persist dir> [config] [--overwrite]");
+        };
+        return run_migrate(&source, args.get(1).map(Path::new), on_conflict);
+    }
+
+    let path = args.first().map(PathBuf::from);
+    let config = Config::from_env_or_file(path.as_deref())?;
+    let storage = LocalStorage::new(&config.storage_dir)?;
+
+    let mut client = create_client(&config, storage.into()).await?;
+    client.start().await?;
+
+    Ok(())
+}
+
+fn run_migrate(source: &Path, config: Option<&Path>, on_conflict: OnConflict) -> Result<()> {
+    let config = Config::from_env_or_file(config)?;
+    let storage = LocalStorage::new(&config.storage_dir)?;
+    let report = migrate(source, &storage.into(), on_conflict)?;
+    print!("{report}");
+    Ok(())
+}
@@ -0,0 +1,183 @@
+use futures::future::join_all;
+use serenity::client::Cache;
+
+use super::{prelude::*, report_failure, resolve_game, respond_game_choices};
+use crate::{
+    achievements::{create_page, AchievementsStore, Progress},
+    app_index::AppIndex,
+    group::{load_users, voice_members},
+    steam::{Game, SteamApiClient},
+    storage::Storage,
+    store::StoreApiClient,
+};
+
+pub const COMMAND: &str = "achievements";
+
+pub async fn run(
+    ctx: impl AsRef<Cache> + AsRef<Http>,
+    command: &ApplicationCommandInteraction,
+    steam: &SteamApiClient,
+    steam_store: &StoreApiClient,
+    app_index: &AppIndex,
+    storage: &Storage,
+) -> Result<()> {
+    let input = command
+        .data
+        .options
+        .iter()
+        .find(|opt| opt.name == "game")
+        .and_then(|opt| opt.value.as_ref())
+        .and_then(|v| v.as_str())
+        .unwrap_or_default()
+        .trim()
+        .to_string();
+
+    let Some(guild) = command.guild_id.and_then(|id| id.to_guild_cached(&ctx)) else {
+        command
+            .create_interaction_response(&ctx, |resp| {
+                resp.kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|int| {
+                        int.ephemeral(true)
+                            .content("サーバーの内のチャンネルで呼び出してください。")
+                    })
+            })
+            .await?;
+        return Ok(());
+    };
+
+    let Some(channel_id) = guild
+        .voice_states
+        .get(&command.user.id)
+        .and_then(|s| s.channel_id)
+    else {
+        command
+            .create_interaction_response(&ctx, |resp| {
+                resp.kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|int| {
+                        int.ephemeral(true)
+                            .content("通話チャンネルにいる状態で呼び出してください。")
+                    })
+            })
+            .await?;
+        return Ok(());
+    };
+
+    // メンバーごとに実績を問い合わせるので、先に応答しておく
+    command
+        .create_interaction_response(&ctx, |resp| {
+            resp.kind(InteractionResponseType::DeferredChannelMessageWithSource)
+                .interaction_response_data(|msg| msg.ephemeral(true))
+        })
+        .await?;
+
+    // 先に応答したので、失敗しても考え中の表示のままにしない
+    let result = async {
+        let Some((appid, name)) = resolve_game(
+            &input,
+            command.user.id,
+            steam,
+            steam_store,
+            app_index,
+            storage,
+        )
+        .await?
+        else {
+            command
+                .edit_original_interaction_response(&ctx, |msg| {
+                    msg.content(format!(
+                        "「{input}」というゲームが見つかりませんでした。候補から選んでください。"
+                    ))
+                })
+                .await?;
+            return Ok(());
+        };
+
+        let achievements = steam.get_schema_for_game(appid).await?;
+        if achievements.is_empty() {
+            command
+                .edit_original_interaction_response(&ctx, |msg| {
+                    msg.content(format!("{name} には実績がありません。"))
+                })
+                .await?;
+            return Ok(());
+        }
+
+        let users = load_users(voice_members(&guild, channel_id), storage);
+        let unlocked = join_all(
+            users
+                .iter()
+                .map(|(_, user)| steam.get_player_achievements(user.steam_id(), appid)),
+        )
+        .await;
+        let progress = users
+            .into_iter()
+            .zip(unlocked)
+            .map(|((member, _), unlocked)| {
+                let unlocked =
+                    unlocked
+                        .map_err(|e| tracing::warn!("{e:?}"))
+                        .ok()
+                        .map(|achievements| {
+                            achievements
+                                .into_iter()
+                                .filter(|achievement| achievement.achieved != 0)
+                                .map(|achievement| achievement.apiname)
+                                .collect()
+                        });
+                Progress { member, unlocked }
+            })
+            .collect();
+
+        let store = AchievementsStore::new(
+            command.user.id,
+            Game { appid, name },
+            achievements,
+            progress,
+        );
+        let key = AchievementsStore::generate_persist_key(command.id);
+        store.save(&key, storage)?;
+
+        let (embed, components) = create_page(&key, &store, 0);
+        command
+            .edit_original_interaction_response(&ctx, |msg| {
+                msg.set_embed(embed).components(|c| {
+                    *c = components;
+                    c
+                })
+            })
+            .await?;
+
+        anyhow::Ok(())
+    }
+    .await;
+    if let Err(e) = result {
+        return report_failure(&ctx, command, e).await;
+    }
+    Ok(())
+}
+
+pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command
+        .name(COMMAND)
+        .description("通話中のメンバーの実績の進み具合と、誰も解除していない実績を表示します。")
+        .create_option(|option| {
+            option
+                .name("game")
+                .description("調べるゲーム")
+                .kind(CommandOptionType::String)
+                .required(true)
+                .set_autocomplete(true)
+        })
+}
+
+/// `game` の補完
+pub async fn autocomplete(
+    ctx: impl AsRef<Http>,
+    autocomplete: &AutocompleteInteraction,
+    steam: &SteamApiClient,
+    steam_store: &StoreApiClient,
+    app_index: &AppIndex,
+    storage: &Storage,
+) -> Result<()> {
+    respond_game_choices(ctx, autocomplete, steam, steam_store, app_index, storage).await
+}
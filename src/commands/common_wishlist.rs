@@ -0,0 +1,177 @@
+use std::collections::HashMap;
+
+use serenity::client::Cache;
+
+use super::prelude::*;
+use crate::{
+    app_index::AppIndex,
+    common_games::{
+        create_page, AppId, CommonGamesStore, Layout, Member, DEFAULT_PAGE_SIZE,
+        MAX_DETAILED_PAGE_SIZE, MAX_PAGE_SIZE,
+    },
+    group::{common_wishlist, load_libraries_and_wishlists, voice_members},
+    hidden::HiddenGames,
+    steam::{Game, Library, SteamApiClient},
+    storage::Storage,
+    store::StoreApiClient,
+};
+
+pub const COMMAND: &str = "common-wishlist";
+
+pub async fn run(
+    ctx: impl AsRef<Cache> + AsRef<Http>,
+    command: &ApplicationCommandInteraction,
+    steam: &SteamApiClient,
+    steam_store: &StoreApiClient,
+    app_index: &AppIndex,
+    storage: &Storage,
+) -> Result<()> {
+    let page_size = command
+        .data
+        .options
+        .iter()
+        .find(|opt| opt.name == "page-size")
+        .and_then(|opt| opt.value.as_ref())
+        .and_then(|v| v.as_u64())
+        .map(|v| v as usize);
+    // 割引を見たいので、省略時は詳しく表示する
+    let detailed = command
+        .data
+        .options
+        .iter()
+        .find(|opt| opt.name == "detail")
+        .and_then(|opt| opt.value.as_ref())
+        .and_then(|v| v.as_bool())
+        .unwrap_or(true);
+
+    let Some(guild) = command.guild_id.and_then(|id| id.to_guild_cached(&ctx)) else {
+        command
+            .create_interaction_response(&ctx, |resp| {
+                resp.kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|int| {
+                        int.ephemeral(true)
+                            .content("サーバーの内のチャンネルで呼び出してください。")
+                    })
+            })
+            .await?;
+        return Ok(());
+    };
+
+    let Some(channel_id) = guild
+        .voice_states
+        .get(&command.user.id)
+        .and_then(|s| s.channel_id)
+    else {
+        command
+            .create_interaction_response(&ctx, |resp| {
+                resp.kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|int| {
+                        int.ephemeral(true)
+                            .content("通話チャンネルにいる状態で呼び出してください。")
+                    })
+            })
+            .await?;
+        return Ok(());
+    };
+
+    // ライブラリやストアの情報の取得に時間がかかるので、先に応答しておく
+    command
+        .create_interaction_response(&ctx, |resp| {
+            resp.kind(InteractionResponseType::DeferredChannelMessageWithSource)
+                .interaction_response_data(|msg| msg.ephemeral(true))
+        })
+        .await?;
+
+    // ライブラリとウィッシュリストの両方を読めたメンバーだけで比べる
+    let (libraries, wishlists) =
+        load_libraries_and_wishlists(voice_members(&guild, channel_id), steam, storage).await;
+    let read_users_count = libraries.len();
+
+    let hidden = HiddenGames::collect(
+        guild.id,
+        libraries.iter().map(|(member, _)| member.user_id),
+        storage,
+    );
+    let appids = common_wishlist(&libraries, &wishlists, &hidden);
+
+    let games = game_names(appids, &libraries, steam_store, app_index).await;
+    let games = steam_store.filter_games(games, usize::MAX).await;
+    let games = CommonGamesStore::wishlist(
+        command.user.id,
+        COMMAND,
+        Layout::new(page_size, detailed),
+        libraries,
+        games,
+    );
+    let key = CommonGamesStore::generate_persist_key(command.id);
+    games.save(&key, storage)?;
+    let details = games.get_details(0, steam_store).await;
+
+    let (embeds, components) = create_page(&key, &games, 0, &details);
+    command
+        .edit_original_interaction_response(&ctx, |msg| {
+            msg.content(format!("通話中のチャンネルにいるメンバーのうち{read_users_count}人のウィッシュリストを読むことができました"))
+                .set_embeds(embeds)
+                .components(|c| {
+                    *c = components;
+                    c
+                })
+        })
+        .await?;
+
+    Ok(())
+}
+
+pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command
+        .name(COMMAND)
+        .description("通話中のメンバー全員がウィッシュリストに入れているか持っているゲームを表示します。")
+        .create_option(|option| {
+            option
+                .name("page-size")
+                .description(format!(
+                    "1ページに表示するゲームの数 (省略時は{DEFAULT_PAGE_SIZE}件、詳細表示では{MAX_DETAILED_PAGE_SIZE}件まで)"
+                ))
+                .kind(CommandOptionType::Integer)
+                .min_int_value(1)
+                .max_int_value(MAX_PAGE_SIZE)
+        })
+        .create_option(|option| {
+            option
+                .name("detail")
+                .description("価格や割引などストアの情報を表示します。(省略時は表示します)")
+                .kind(CommandOptionType::Boolean)
+        })
+}
+
+/// ウィッシュリストには名前が入っていないので、ライブラリ、アプリの一覧、ストアの順に引く
+///
+/// 名前がわからなかったゲームは除く
+async fn game_names(
+    appids: Vec<AppId>,
+    libraries: &[(Member, Library)],
+    steam_store: &StoreApiClient,
+    app_index: &AppIndex,
+) -> Vec<Game> {
+    let mut names = HashMap::new();
+    let mut unknown = Vec::new();
+    for appid in appids {
+        let name = libraries
+            .iter()
+            .find_map(|(_, library)| Some(library.get(&appid)?.game.name.clone()))
+            .or_else(|| app_index.name(appid));
+        match name {
+            Some(name) => {
+                names.insert(appid, name);
+            }
+            None => unknown.push(appid),
+        }
+    }
+    for (appid, details) in steam_store.get_app_details_many(unknown).await {
+        names.insert(appid, details.name);
+    }
+    names
+        .into_iter()
+        .map(|(appid, name)| Game { appid, name })
+        .collect()
+}
@@ -0,0 +1,151 @@
+use anyhow::bail;
+use rand::{distributions::Alphanumeric, Rng};
+
+use super::prelude::*;
+use crate::{
+    storage::{LoadError, Storage},
+    user::User,
+};
+
+pub const COMMAND: &str = "family";
+
+/// Steam ファミリーに参加できる人数の上限
+const MAX_FAMILY_MEMBERS: usize = 6;
+
+/// 合言葉の長さ
+const CODE_LEN: usize = 12;
+
+pub async fn run(
+    ctx: impl AsRef<Http>,
+    command: &ApplicationCommandInteraction,
+    storage: &Storage,
+) -> Result<()> {
+    let Some(subcommand) = command.data.options.first() else {
+        bail!("subcommand is missing.");
+    };
+    let code = subcommand
+        .options
+        .iter()
+        .find(|opt| opt.name == "code")
+        .and_then(|opt| opt.value.as_ref())
+        .and_then(|v| v.as_str())
+        .map(|v| v.trim().to_string())
+        .unwrap_or_default();
+
+    let discord_id = command.user.id.to_string();
+    let content = match User::load(&discord_id, storage) {
+        Err(LoadError::Missing) => "先に `/register` で Steam ID を登録してください。".to_string(),
+        Err(e @ LoadError::Corrupt(_)) => {
+            tracing::warn!("{e:?}");
+            "登録されている情報を読み込めませんでした。もう一度 `/register` してください。"
+                .to_string()
+        }
+        Ok(mut user) => match subcommand.name.as_str() {
+            "create" => {
+                let code = generate_code();
+                user.set_family(Some(code.clone()));
+                user.save(&discord_id, storage)?;
+                format!(
+                    "ファミリーを作りました。ほかのメンバーには `/family join code:{code}` で参加してもらってください。"
+                )
+            }
+            "join" => {
+                let members = family_members(&code, storage)?;
+                if members.is_empty() {
+                    format!("「{code}」のファミリーは見つかりませんでした。")
+                } else if members.len() >= MAX_FAMILY_MEMBERS && !members.contains(&discord_id) {
+                    format!("ファミリーには{MAX_FAMILY_MEMBERS}人までしか参加できません。")
+                } else {
+                    user.set_family(Some(code));
+                    user.save(&discord_id, storage)?;
+                    "ファミリーに参加しました。ファミリーのメンバーが持っているゲームは `/get-common-games` で持っているものとして扱います。".to_string()
+                }
+            }
+            "leave" => {
+                user.set_family(None);
+                user.save(&discord_id, storage)?;
+                "ファミリーから抜けました。".to_string()
+            }
+            _ => match user.family() {
+                None => "ファミリーに参加していません。".to_string(),
+                Some(code) => {
+                    let members = family_members(code, storage)?
+                        .iter()
+                        .map(|id| format!("<@{id}>"))
+                        .collect::<Vec<_>>()
+                        .join("、");
+                    format!("合言葉: `{code}`\nメンバー: {members}")
+                }
+            },
+        },
+    };
+
+    command
+        .create_interaction_response(ctx, |response| {
+            response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|msg| msg.ephemeral(true).content(content))
+        })
+        .await?;
+
+    Ok(())
+}
+
+pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command
+        .name(COMMAND)
+        .description(
+            "Steam ファミリーを登録して、ファミリーのゲームを持っているものとして扱います。",
+        )
+        .create_option(|option| {
+            option
+                .name("create")
+                .description("ファミリーを作って合言葉を発行します。")
+                .kind(CommandOptionType::SubCommand)
+        })
+        .create_option(|option| {
+            option
+                .name("join")
+                .description("合言葉でファミリーに参加します。")
+                .kind(CommandOptionType::SubCommand)
+                .create_sub_option(|option| {
+                    option
+                        .name("code")
+                        .description("ファミリーを作った人に発行された合言葉")
+                        .kind(CommandOptionType::String)
+                        .required(true)
+                })
+        })
+        .create_option(|option| {
+            option
+                .name("leave")
+                .description("ファミリーから抜けます。")
+                .kind(CommandOptionType::SubCommand)
+        })
+        .create_option(|option| {
+            option
+                .name("show")
+                .description("参加しているファミリーのメンバーを表示します。")
+                .kind(CommandOptionType::SubCommand)
+        })
+}
+
+/// ファミリーの合言葉を作る
+///
+/// 知らない人が当てて参加できないように、英数字をランダムに並べる
+fn generate_code() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(CODE_LEN)
+        .map(char::from)
+        .collect()
+}
+
+/// ファミリーに参加しているユーザーの Discord の ID
+fn family_members(code: &str, storage: &Storage) -> Result<Vec<String>> {
+    Ok(User::load_all(storage)?
+        .into_iter()
+        .filter(|(_, user)| user.family() == Some(code))
+        .map(|(discord_id, _)| discord_id)
+        .collect())
+}
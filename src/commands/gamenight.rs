@@ -0,0 +1,114 @@
+use anyhow::bail;
+
+use super::prelude::*;
+use crate::{game_night::GameNight, storage::Storage};
+
+pub const COMMAND: &str = "gamenight";
+
+/// 開始時刻のタイムゾーン
+const UTC_OFFSET: &str = "+09:00";
+
+pub async fn run(
+    ctx: impl AsRef<Http>,
+    command: &ApplicationCommandInteraction,
+    storage: &Storage,
+) -> Result<()> {
+    let Some(subcommand) = command.data.options.first() else {
+        bail!("subcommand is missing.");
+    };
+    let option = |name: &str| {
+        subcommand
+            .options
+            .iter()
+            .find(|opt| opt.name == name)
+            .and_then(|opt| opt.value.as_ref())
+            .and_then(|v| v.as_str())
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+    };
+    let title = option("title").unwrap_or_else(|| "ゲーム会".to_string());
+    let starts_at = option("start").and_then(|start| parse_start(&start));
+
+    let notice = match (command.guild_id, starts_at) {
+        (None, _) => Some("サーバーの内のチャンネルで呼び出してください。"),
+        (_, None) => Some("開始時刻は `2024-01-31 21:00` の形で入力してください。"),
+        (_, Some(starts_at)) if starts_at.unix_timestamp() <= Timestamp::now().unix_timestamp() => {
+            Some("開始時刻には未来の時刻を入力してください。")
+        }
+        _ => None,
+    };
+    if let Some(notice) = notice {
+        command
+            .create_interaction_response(&ctx, |response| {
+                response
+                    .kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|msg| msg.ephemeral(true).content(notice))
+            })
+            .await?;
+        return Ok(());
+    }
+    let (Some(guild_id), Some(starts_at)) = (command.guild_id, starts_at) else {
+        return Ok(());
+    };
+
+    let game_night = GameNight::new(
+        guild_id,
+        command.channel_id,
+        title,
+        starts_at,
+        command.user.id,
+    );
+    let key = GameNight::generate_persist_key(command.id);
+    game_night.save(&key, storage)?;
+
+    // 全員が参加できるように、募集はチャンネルに見えるように投稿する
+    let (embed, components) = game_night.create_message(&key);
+    command
+        .create_interaction_response(&ctx, |response| {
+            response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|msg| msg.set_embed(embed).set_components(components))
+        })
+        .await?;
+
+    // 開始したときにボタンを消すため、募集のメッセージを覚えておく
+    // 投稿してからボタンで参加者が変わっていることがあるので読みなおす
+    // もう始まっていたら、締め切りの処理が済んでいるので何もしない
+    let message = command.get_interaction_response(&ctx).await?;
+    GameNight::update(&key, storage, |game_night| {
+        game_night.message_id = Some(message.id);
+    })?;
+
+    Ok(())
+}
+
+pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command
+        .name(COMMAND)
+        .description("ゲーム会の参加者を募って、開始時刻に参加者の共通のゲームを投稿します。")
+        .create_option(|option| {
+            option
+                .name("schedule")
+                .description("ゲーム会の参加者を募ります。")
+                .kind(CommandOptionType::SubCommand)
+                .create_sub_option(|option| {
+                    option
+                        .name("start")
+                        .description("開始時刻 (日本時間、例: 2024-01-31 21:00)")
+                        .kind(CommandOptionType::String)
+                        .required(true)
+                })
+                .create_sub_option(|option| {
+                    option
+                        .name("title")
+                        .description("ゲーム会の名前 (省略時は「ゲーム会」)")
+                        .kind(CommandOptionType::String)
+                })
+        })
+}
+
+/// `2024-01-31 21:00` の形の日本時間を読む
+fn parse_start(input: &str) -> Option<Timestamp> {
+    let (date, time) = input.split_once(' ')?;
+    Timestamp::parse(&format!("{date}T{}:00{UTC_OFFSET}", time.trim())).ok()
+}
@@ -1,14 +1,17 @@
-use std::collections::HashSet;
-
-use futures::future::join_all;
 use serenity::client::Cache;
-use shuttle_persist::PersistInstance;
 
-use super::prelude::*;
+use super::{choice_name, focused_input, prelude::*, search_library};
 use crate::{
-    common_games::{create_interaction_response, CommonGamesButtonCustomId, CommonGamesStore},
+    common_games::{
+        create_page, CommonGamesStore, Filter, Layout, DEFAULT_PAGE_SIZE, MAX_DETAILED_PAGE_SIZE,
+        MAX_PAGE_SIZE,
+    },
+    free_games::free_multiplayer_games,
+    group::{load_stored_libraries_with, share_family_libraries, voice_members},
+    hidden::HiddenGames,
     steam::SteamApiClient,
-    user::User,
+    storage::Storage,
+    store::StoreApiClient,
 };
 
 pub const COMMAND: &str = "get-common-games";
@@ -17,14 +20,58 @@ pub async fn run(
     ctx: impl AsRef<Cache> + AsRef<Http>,
     command: &ApplicationCommandInteraction,
     steam: &SteamApiClient,
-    persist: &PersistInstance,
+    steam_store: &StoreApiClient,
+    storage: &Storage,
 ) -> Result<()> {
+    let page_size = command
+        .data
+        .options
+        .iter()
+        .find(|opt| opt.name == "page-size")
+        .and_then(|opt| opt.value.as_ref())
+        .and_then(|v| v.as_u64())
+        .map(|v| v as usize);
+    let detailed = command
+        .data
+        .options
+        .iter()
+        .find(|opt| opt.name == "detail")
+        .and_then(|opt| opt.value.as_ref())
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    let played_free = command
+        .data
+        .options
+        .iter()
+        .find(|opt| opt.name == "played-free")
+        .and_then(|opt| opt.value.as_ref())
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    let free_to_play = command
+        .data
+        .options
+        .iter()
+        .find(|opt| opt.name == "free-to-play")
+        .and_then(|opt| opt.value.as_ref())
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    let query = command
+        .data
+        .options
+        .iter()
+        .find(|opt| opt.name == "query")
+        .and_then(|opt| opt.value.as_ref())
+        .and_then(|v| v.as_str())
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty());
+
     let Some(guild_id) = command.guild_id else {
         command
             .create_interaction_response(&ctx, |resp| {
                 resp.kind(InteractionResponseType::ChannelMessageWithSource)
                     .interaction_response_data(|int| {
-                        int.ephemeral(true).content("サーバーの内のチャンネルで呼び出してください。")
+                        int.ephemeral(true)
+                            .content("サーバーの内のチャンネルで呼び出してください。")
                     })
             })
             .await?;
@@ -36,67 +83,99 @@ pub async fn run(
             .create_interaction_response(&ctx, |resp| {
                 resp.kind(InteractionResponseType::ChannelMessageWithSource)
                     .interaction_response_data(|int| {
-                        int.ephemeral(true).content("内部でなにかおかしなことになりました。")
+                        int.ephemeral(true)
+                            .content("内部でなにかおかしなことになりました。")
                     })
             })
             .await?;
         return Ok(());
     };
 
-    let Some(channel_id) = guild.voice_states.get(&command.user.id).and_then(|s| s.channel_id) else {
+    let Some(channel_id) = guild
+        .voice_states
+        .get(&command.user.id)
+        .and_then(|s| s.channel_id)
+    else {
         command
             .create_interaction_response(&ctx, |resp| {
                 resp.kind(InteractionResponseType::ChannelMessageWithSource)
                     .interaction_response_data(|int| {
-                        int.ephemeral(true).content("通話チャンネルにいる状態で呼び出してください。")
+                        int.ephemeral(true)
+                            .content("通話チャンネルにいる状態で呼び出してください。")
                     })
             })
             .await?;
         return Ok(());
     };
 
-    // 呼び出したユーザーが参加している VC にいるすべてのユーザーの ID を取り出す
-    let ids = guild
-        .voice_states
-        .iter()
-        .filter(|(_, s)| s.channel_id == Some(channel_id))
-        .map(|(u, _)| u.to_string())
-        .collect::<HashSet<_>>();
-
-    // Discord の ID から事前に登録された Steam の ID を引く
-    // 引けなかったものは存在しないものとして除外する
-    let users = ids
-        .iter()
-        .flat_map(|id| User::load(id, persist))
-        .collect::<HashSet<_>>();
-
-    let ids = users.iter().map(|u| u.steam_id()).collect::<Vec<_>>();
+    // ライブラリやストアの情報の取得に時間がかかるので、先に応答しておく
+    command
+        .create_interaction_response(&ctx, |resp| {
+            resp.kind(InteractionResponseType::DeferredChannelMessageWithSource)
+                .interaction_response_data(|msg| msg.ephemeral(true))
+        })
+        .await?;
 
-    let games = join_all(ids.iter().map(|id| steam.get_owned_games(id)))
-        .await
-        .into_iter()
-        .flatten()
-        .collect::<Vec<_>>();
-    let read_users_count = games.len();
-    if games.len() < ids.len() {
-        tracing::warn!("1つ以上のユーザーの所有ゲームを取得できませんでした");
+    // 呼び出したユーザーが参加している VC にいるすべてのユーザーのライブラリを読む
+    // ライブラリはバックグラウンドで取得しなおしているので、保存しているものを使う
+    let (mut libraries, failed) = load_stored_libraries_with(
+        voice_members(&guild, channel_id),
+        steam,
+        storage,
+        played_free,
+    )
+    .await;
+    let mut content = format!(
+        "通話中のチャンネルにいるメンバーのうち{}人のsteamライブラリを読むことができました",
+        libraries.len()
+    );
+    // 読めなかった人は一覧に含まれないので、誰が抜けているかわかるようにする
+    if !failed.is_empty() {
+        let names = failed
+            .iter()
+            .map(|member| member.name.as_str())
+            .collect::<Vec<_>>()
+            .join("、");
+        content.push_str(&format!("\nライブラリを読めなかった人: {names}"));
+    }
+    // Steam ファミリーで借りられるゲームも持っているものとして扱う
+    if let Err(e) = share_family_libraries(&mut libraries, steam, storage, played_free).await {
+        tracing::warn!("{e:?}");
     }
 
-    let games = CommonGamesStore::new(games);
-    let key = command.user.id.to_string();
-    games.save(&key, persist)?;
-
-    let games = games.get(0);
-    let custom_id = CommonGamesButtonCustomId::new(0, key);
+    let hidden = HiddenGames::collect(
+        guild_id,
+        libraries.iter().map(|(member, _)| member.user_id),
+        storage,
+    );
+    let mut games = CommonGamesStore::new(
+        command.user.id,
+        COMMAND,
+        Layout::new(page_size, detailed),
+        libraries,
+        &hidden,
+        &if free_to_play {
+            free_multiplayer_games()
+        } else {
+            Vec::new()
+        },
+        steam_store,
+    )
+    .await;
+    if let Some(query) = query {
+        games = games.filter(Filter::by_query(query), steam_store).await;
+    }
+    let key = CommonGamesStore::generate_persist_key(command.id);
+    games.save(&key, storage)?;
+    let details = games.get_details(0, steam_store).await;
 
+    let (embeds, components) = create_page(&key, &games, 0, &details);
     command
-        .create_interaction_response(ctx, |response| {
-            response
-                .kind(InteractionResponseType::ChannelMessageWithSource)
-                .interaction_response_data(|msg| {
-                    create_interaction_response(custom_id, games, true, msg);
-                    msg.content(format!("通話中のチャンネルにいるメンバーのうち{read_users_count}人のsteamライブラリを読むことができました"))
-                })
+        .edit_original_interaction_response(ctx, |msg| {
+            msg.content(content).set_embeds(embeds).components(|c| {
+                *c = components;
+                c
+            })
         })
         .await?;
 
@@ -104,5 +183,67 @@ pub async fn run(
 }
 
 pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
-    command.name(COMMAND).description("あなたがいま参加している通話チャンネルの参加者がすべてが所持しているSteamのゲームを表示します。")
+    command
+        .name(COMMAND)
+        .description("あなたがいま参加している通話チャンネルの参加者がすべてが所持しているSteamのゲームを表示します。")
+        .create_option(|option| {
+            option
+                .name("page-size")
+                .description(format!(
+                    "1ページに表示するゲームの数 (省略時は{DEFAULT_PAGE_SIZE}件、詳細表示では{MAX_DETAILED_PAGE_SIZE}件まで)"
+                ))
+                .kind(CommandOptionType::Integer)
+                .min_int_value(1)
+                .max_int_value(MAX_PAGE_SIZE)
+        })
+        .create_option(|option| {
+            option
+                .name("detail")
+                .description("価格やレビューなどストアの情報を表示します。")
+                .kind(CommandOptionType::Boolean)
+        })
+        .create_option(|option| {
+            option
+                .name("played-free")
+                .description("一度でも遊んだことのある無料のゲームも含めます。")
+                .kind(CommandOptionType::Boolean)
+        })
+        .create_option(|option| {
+            option
+                .name("free-to-play")
+                .description("有名な無料のマルチプレイのゲームを全員が持っているものとして含めます。")
+                .kind(CommandOptionType::Boolean)
+        })
+        .create_option(|option| {
+            option
+                .name("query")
+                .description("名前に含まれる文字で絞り込みます。")
+                .kind(CommandOptionType::String)
+                .set_autocomplete(true)
+        })
+}
+
+/// `query` の補完
+///
+/// 共通のゲームを求めるのは時間がかかるので、呼び出したユーザーのライブラリから候補を出す
+pub async fn autocomplete(
+    ctx: impl AsRef<Http>,
+    autocomplete: &AutocompleteInteraction,
+    steam: &SteamApiClient,
+    storage: &Storage,
+) -> Result<()> {
+    let input = focused_input(autocomplete);
+    let games = search_library(steam, storage, autocomplete.user.id, &input).await?;
+
+    autocomplete
+        .create_autocomplete_response(ctx, |response| {
+            for (_, name) in games {
+                // 値も 100 文字までなので名前と同じく切り詰める
+                let name = choice_name(&name);
+                response.add_string_choice(&name, &name);
+            }
+            response
+        })
+        .await?;
+    Ok(())
 }
@@ -7,6 +7,22 @@ const HOW_TO_USE: &str = r#"
 1. [アカウント詳細](https://store.steampowered.com/account/)にアクセスして、左上にある*Steam ID*をコピーしておく
 1. このbotとのチャットを開き、`/register` を入力する。 `steam-id` にさきほどコピーした*Steam ID*を貼り付け送信する。
 1. 通話に参加し、そのサーバー内で `/get-common-games` を入力することで共通のゲーム一覧を取得できます。
+
+一覧の下のメニューから遊ぶゲームを選ぶと、起動やロビーに参加するリンクをチャンネルに投稿します。
+`/common-wishlist` で全員がウィッシュリストに入れているか持っているゲームを表示できます。
+`/near-misses` で少しの人だけが持っていないゲームを、揃えるのに安い順に表示できます。
+`/recent` で最近何人もが遊んでいるゲームと、通話中のメンバーが最近買ったゲームを表示できます。
+`/now-playing` で通話中のメンバーがいま遊んでいるゲームと、全員で合流できるゲームを表示できます。
+`/achievements` で通話中のメンバーの実績の進み具合と、誰も解除していない実績を比べられます。
+`/leaderboard` でサーバーのメンバーをプレイ時間の順に並べられます。
+`/gamenight schedule` でゲーム会の参加者を募ると、開始時刻に参加者の共通のゲームを投稿します。
+`/lfg` でゲームを一緒に遊ぶ人を募集できます (`/lfg-notify` で持っているゲームの募集のときに呼ばれるようにできます)。
+`/sale-alerts` でそれらのゲームがセールになったときにチャンネルに通知できます。
+`/who-owns` でだれが特定のゲームを持っているかを調べられます。
+Steam ファミリーに参加しているときは `/family` で登録すると、ファミリーのゲームも持っているものとして扱います。
+遊ばないゲームは `/hide-game` で一覧に表示しないようにできます (`/unhide-game` で戻せます)。
+ツールやサウンドトラック、DLC、専用サーバーなどはもともと表示しません。
+`/get-common-games` のライブラリは数時間ごとに取得しなおしているので、買ったばかりのゲームはすぐには表示されないことがあります。
 "#;
 
 pub async fn run(ctx: impl AsRef<Http>, command: &ApplicationCommandInteraction) -> Result<()> {
@@ -14,9 +30,10 @@ pub async fn run(ctx: impl AsRef<Http>, command: &ApplicationCommandInteraction)
         .create_interaction_response(ctx, |response| {
             response
                 .kind(InteractionResponseType::ChannelMessageWithSource)
+                // フィールドは 1024 文字までなので、長い説明は本文に入れる
                 .interaction_response_data(|msg| {
                     msg.ephemeral(true)
-                        .embed(|embed| embed.field("使い方", HOW_TO_USE, true))
+                        .embed(|embed| embed.title("使い方").description(HOW_TO_USE))
                 })
         })
         .await?;
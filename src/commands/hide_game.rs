@@ -0,0 +1,157 @@
+use super::{prelude::*, report_failure, resolve_game, respond_game_choices};
+use crate::{
+    app_index::AppIndex,
+    hidden::{HiddenGames, HideScope},
+    steam::SteamApiClient,
+    storage::Storage,
+    store::StoreApiClient,
+};
+
+pub const COMMAND: &str = "hide-game";
+
+pub async fn run(
+    ctx: impl AsRef<Http>,
+    command: &ApplicationCommandInteraction,
+    steam: &SteamApiClient,
+    steam_store: &StoreApiClient,
+    app_index: &AppIndex,
+    storage: &Storage,
+) -> Result<()> {
+    let input = command
+        .data
+        .options
+        .iter()
+        .find(|opt| opt.name == "game")
+        .and_then(|opt| opt.value.as_ref())
+        .and_then(|v| v.as_str())
+        .unwrap_or_default()
+        .trim()
+        .to_string();
+
+    // ライブラリやストアから名前を引くのに時間がかかることがあるので、先に応答しておく
+    command
+        .create_interaction_response(&ctx, |resp| {
+            resp.kind(InteractionResponseType::DeferredChannelMessageWithSource)
+                .interaction_response_data(|msg| msg.ephemeral(true))
+        })
+        .await?;
+
+    // 先に応答したので、失敗しても考え中の表示のままにしない
+    let result = async {
+        let content = match parse_scope(command) {
+            Err(notice) => notice.to_string(),
+            Ok(scope) => {
+                match resolve_game(
+                    &input,
+                    command.user.id,
+                    steam,
+                    steam_store,
+                    app_index,
+                    storage,
+                )
+                .await?
+                {
+                    None => format!(
+                        "「{input}」というゲームが見つかりませんでした。候補から選んでください。"
+                    ),
+                    Some((appid, name)) => {
+                        let mut hidden = HiddenGames::load(scope, storage)?;
+                        let inserted = hidden.insert(appid, name.clone());
+                        hidden.save(scope, storage)?;
+                        match (inserted, scope) {
+                            (false, _) => format!("{name} はすでに非表示にしています。"),
+                            (true, HideScope::User(_)) => {
+                                format!("{name} をあなたが参加する一覧に表示しないようにしました。")
+                            }
+                            (true, HideScope::Guild(_)) => {
+                                format!("{name} をこのサーバーの一覧に表示しないようにしました。")
+                            }
+                        }
+                    }
+                }
+            }
+        };
+
+        command
+            .edit_original_interaction_response(&ctx, |msg| msg.content(content))
+            .await?;
+
+        anyhow::Ok(())
+    }
+    .await;
+    if let Err(e) = result {
+        return report_failure(&ctx, command, e).await;
+    }
+    Ok(())
+}
+
+pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command
+        .name(COMMAND)
+        .description("共通のゲームの一覧に表示しないゲームを追加します。")
+        .create_option(|option| {
+            option
+                .name("game")
+                .description("表示しないゲーム")
+                .kind(CommandOptionType::String)
+                .required(true)
+                .set_autocomplete(true)
+        })
+        .create_option(scope_option)
+}
+
+/// `game` の補完
+pub async fn autocomplete(
+    ctx: impl AsRef<Http>,
+    autocomplete: &AutocompleteInteraction,
+    steam: &SteamApiClient,
+    steam_store: &StoreApiClient,
+    app_index: &AppIndex,
+    storage: &Storage,
+) -> Result<()> {
+    respond_game_choices(ctx, autocomplete, steam, steam_store, app_index, storage).await?;
+    Ok(())
+}
+
+/// `/hide-game` と `/unhide-game` で共通の `scope` オプション
+pub(super) fn scope_option(
+    option: &mut serenity::builder::CreateApplicationCommandOption,
+) -> &mut serenity::builder::CreateApplicationCommandOption {
+    option
+        .name("scope")
+        .description("自分だけか、サーバー全体か (省略時は自分だけ)")
+        .kind(CommandOptionType::String)
+        .add_string_choice("自分", "user")
+        .add_string_choice("サーバー", "guild")
+}
+
+/// `scope` オプションを読む
+///
+/// サーバーのリストを変更できないときは利用者に返す文言を返す
+pub(super) fn parse_scope(
+    command: &ApplicationCommandInteraction,
+) -> std::result::Result<HideScope, &'static str> {
+    let scope = command
+        .data
+        .options
+        .iter()
+        .find(|opt| opt.name == "scope")
+        .and_then(|opt| opt.value.as_ref())
+        .and_then(|v| v.as_str());
+    if scope != Some("guild") {
+        return Ok(HideScope::User(command.user.id));
+    }
+
+    let Some(guild_id) = command.guild_id else {
+        return Err("サーバーのリストはサーバーの内のチャンネルで変更してください。");
+    };
+    let can_manage = command
+        .member
+        .as_ref()
+        .and_then(|m| m.permissions)
+        .is_some_and(|p| p.manage_guild());
+    if !can_manage {
+        return Err("サーバーのリストを変更するにはサーバーの管理権限が必要です。");
+    }
+    Ok(HideScope::Guild(guild_id))
+}
@@ -0,0 +1,231 @@
+use serenity::client::Context;
+
+use super::{playtime_text, prelude::*, report_failure, resolve_game, respond_game_choices};
+use crate::{
+    app_index::AppIndex,
+    common_games::{store_url, Member},
+    group::{load_libraries, registered_members},
+    playtime::{PlaytimeHistory, WEEK},
+    steam::SteamApiClient,
+    storage::Storage,
+    store::StoreApiClient,
+};
+
+pub const COMMAND: &str = "leaderboard";
+
+/// 表示する人数
+const MAX_ENTRIES: usize = 20;
+
+struct Entry {
+    member: Member,
+    /// 合計のプレイ時間 (分)
+    playtime: u64,
+    /// 1 週間前の記録からの増え方 (分)
+    ///
+    /// 記録がまだないときは `None`
+    weekly: Option<u64>,
+}
+
+pub async fn run(
+    ctx: &Context,
+    command: &ApplicationCommandInteraction,
+    steam: &SteamApiClient,
+    steam_store: &StoreApiClient,
+    app_index: &AppIndex,
+    storage: &Storage,
+) -> Result<()> {
+    let input = command
+        .data
+        .options
+        .iter()
+        .find(|opt| opt.name == "game")
+        .and_then(|opt| opt.value.as_ref())
+        .and_then(|v| v.as_str())
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty());
+    let weekly_order = command
+        .data
+        .options
+        .iter()
+        .find(|opt| opt.name == "order")
+        .and_then(|opt| opt.value.as_ref())
+        .and_then(|v| v.as_str())
+        == Some("weekly");
+
+    let Some(guild) = command.guild_id.and_then(|id| id.to_guild_cached(ctx)) else {
+        command
+            .create_interaction_response(ctx, |resp| {
+                resp.kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|int| {
+                        int.ephemeral(true)
+                            .content("サーバーの内のチャンネルで呼び出してください。")
+                    })
+            })
+            .await?;
+        return Ok(());
+    };
+
+    // ライブラリの取得に時間がかかるので、先に応答しておく
+    command
+        .create_interaction_response(ctx, |resp| {
+            resp.kind(InteractionResponseType::DeferredChannelMessageWithSource)
+                .interaction_response_data(|msg| msg.ephemeral(true))
+        })
+        .await?;
+
+    // 先に応答したので、失敗しても考え中の表示のままにしない
+    let result = async {
+        let game = match input {
+            Some(input) => {
+                let Some(game) = resolve_game(
+                    &input,
+                    command.user.id,
+                    steam,
+                    steam_store,
+                    app_index,
+                    storage,
+                )
+                .await?
+                else {
+                    command
+                        .edit_original_interaction_response(ctx, |msg| {
+                            msg.content(format!(
+                                "「{input}」というゲームが見つかりませんでした。候補から選んでください。"
+                            ))
+                        })
+                        .await?;
+                    return Ok(());
+                };
+                Some(game)
+            }
+            None => None,
+        };
+        let appid = game.as_ref().map(|(appid, _)| *appid);
+
+        let members = registered_members(ctx, &guild, storage).await?;
+        let (libraries, _) = load_libraries(members, steam, storage).await;
+        let mut entries = libraries
+            .into_iter()
+            .filter_map(|(member, library)| {
+                let playtime = match appid {
+                    Some(appid) => library.get(&appid)?.playtime_forever,
+                    None => library.values().map(|game| game.playtime_forever).sum(),
+                };
+                // 記録が読めなくても、合計のプレイ時間は表示する
+                let weekly = match PlaytimeHistory::load(&member.user_id.to_string(), storage) {
+                    Ok(history) => history
+                        .since(WEEK)
+                        .map(|snapshot| playtime.saturating_sub(snapshot.playtime(appid))),
+                    Err(e) => {
+                        tracing::warn!("{e:?}");
+                        None
+                    }
+                };
+                Some(Entry {
+                    member,
+                    playtime,
+                    weekly,
+                })
+            })
+            .collect::<Vec<_>>();
+        entries.sort_by(|a, b| {
+            let key = |entry: &Entry| {
+                if weekly_order {
+                    (entry.weekly.unwrap_or_default(), entry.playtime)
+                } else {
+                    (entry.playtime, entry.weekly.unwrap_or_default())
+                }
+            };
+            key(b)
+                .cmp(&key(a))
+                .then_with(|| a.member.name.cmp(&b.member.name))
+        });
+        let count = entries.len();
+        entries.truncate(MAX_ENTRIES);
+
+        let description = if entries.is_empty() {
+            "プレイ時間を読めたメンバーがいませんでした。".to_string()
+        } else {
+            entries
+                .iter()
+                .enumerate()
+                .map(|(rank, entry)| {
+                    let weekly = match entry.weekly {
+                        Some(weekly) => format!(" (今週 +{})", playtime_text(weekly)),
+                        None => String::new(),
+                    };
+                    format!(
+                        "{}. {} — {}{weekly}",
+                        rank + 1,
+                        entry.member.name,
+                        playtime_text(entry.playtime)
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        let order = if weekly_order {
+            "今週のプレイ時間順"
+        } else {
+            "合計のプレイ時間順"
+        };
+
+        command
+            .edit_original_interaction_response(ctx, |msg| {
+                msg.embed(|embed| {
+                    match &game {
+                        Some((appid, name)) => embed
+                            .title(format!("{name} のプレイ時間ランキング"))
+                            .url(store_url(*appid)),
+                        None => embed.title("プレイ時間ランキング"),
+                    };
+                    embed.description(description).footer(|f| {
+                        f.text(format!(
+                            "{count}人中・{order}・今週の増え方は毎日の記録と比べています"
+                        ))
+                    })
+                })
+            })
+            .await?;
+
+        anyhow::Ok(())
+    }
+    .await;
+    if let Err(e) = result {
+        return report_failure(ctx, command, e).await;
+    }
+    Ok(())
+}
+
+pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command
+        .name(COMMAND)
+        .description("Steam IDを登録しているサーバーのメンバーをプレイ時間の順に並べます。")
+        .create_option(|option| {
+            option
+                .name("game")
+                .description("プレイ時間を比べるゲーム (省略時はすべてのゲームの合計)")
+                .kind(CommandOptionType::String)
+                .set_autocomplete(true)
+        })
+        .create_option(|option| {
+            option
+                .name("order")
+                .description("並べる順 (省略時は合計のプレイ時間)")
+                .kind(CommandOptionType::String)
+                .add_string_choice("合計のプレイ時間", "total")
+                .add_string_choice("今週のプレイ時間", "weekly")
+        })
+}
+
+/// `game` の補完
+pub async fn autocomplete(
+    ctx: impl AsRef<Http>,
+    autocomplete: &AutocompleteInteraction,
+    steam: &SteamApiClient,
+    steam_store: &StoreApiClient,
+    app_index: &AppIndex,
+    storage: &Storage,
+) -> Result<()> {
+    respond_game_choices(ctx, autocomplete, steam, steam_store, app_index, storage).await
+}
@@ -0,0 +1,168 @@
+use serenity::client::Context;
+
+use super::{prelude::*, resolve_game, respond_game_choices};
+use crate::{
+    app_index::AppIndex,
+    group::{load_libraries, load_users, registered_members},
+    lfg::{Lfg, LFG_TTL},
+    steam::SteamApiClient,
+    storage::Storage,
+    store::StoreApiClient,
+};
+
+pub const COMMAND: &str = "lfg";
+
+/// 募集できる人数の上限
+const MAX_SLOTS: usize = 15;
+
+pub async fn run(
+    ctx: &Context,
+    command: &ApplicationCommandInteraction,
+    steam: &SteamApiClient,
+    steam_store: &StoreApiClient,
+    app_index: &AppIndex,
+    storage: &Storage,
+) -> Result<()> {
+    let input = command
+        .data
+        .options
+        .iter()
+        .find(|opt| opt.name == "game")
+        .and_then(|opt| opt.value.as_ref())
+        .and_then(|v| v.as_str())
+        .unwrap_or_default()
+        .trim()
+        .to_string();
+    let slots = command
+        .data
+        .options
+        .iter()
+        .find(|opt| opt.name == "slots")
+        .and_then(|opt| opt.value.as_ref())
+        .and_then(|v| v.as_u64())
+        .map(|v| v as usize)
+        .unwrap_or(1)
+        .clamp(1, MAX_SLOTS);
+
+    let Some(guild) = command.guild_id.and_then(|id| id.to_guild_cached(ctx)) else {
+        command
+            .create_interaction_response(ctx, |resp| {
+                resp.kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|int| {
+                        int.ephemeral(true)
+                            .content("サーバーの内のチャンネルで呼び出してください。")
+                    })
+            })
+            .await?;
+        return Ok(());
+    };
+
+    // 持っている人を調べるのに時間がかかるので、先に応答しておく
+    // 募集は全員に見えるように投稿する
+    command
+        .create_interaction_response(ctx, |resp| {
+            resp.kind(InteractionResponseType::DeferredChannelMessageWithSource)
+        })
+        .await?;
+
+    let Some((appid, name)) = resolve_game(
+        &input,
+        command.user.id,
+        steam,
+        steam_store,
+        app_index,
+        storage,
+    )
+    .await?
+    else {
+        command
+            .edit_original_interaction_response(ctx, |msg| {
+                msg.content(format!(
+                    "「{input}」というゲームが見つかりませんでした。候補から選んでください。"
+                ))
+            })
+            .await?;
+        return Ok(());
+    };
+
+    // 通知を受け取ると決めていて、ゲームを持っているメンバーを呼ぶ
+    let members = registered_members(ctx, &guild, storage)
+        .await?
+        .into_iter()
+        .filter(|member| member.user_id != command.user.id)
+        .collect();
+    let opted_in = load_users(members, storage)
+        .into_iter()
+        .filter(|(_, user)| user.lfg_notifications())
+        .map(|(member, _)| member)
+        .collect();
+    let (libraries, _) = load_libraries(opted_in, steam, storage).await;
+    let mentions = libraries
+        .iter()
+        .filter(|(_, library)| library.contains_key(&appid))
+        .map(|(member, _)| format!("<@{}>", member.user_id))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let lfg = Lfg::new(appid, name, slots, command.channel_id, command.user.id);
+    let key = Lfg::generate_persist_key(command.id);
+    lfg.save(&key, storage)?;
+
+    let (embed, components) = lfg.create_message(&key, false);
+    let message = command
+        .edit_original_interaction_response(ctx, |msg| {
+            msg.content(mentions).set_embed(embed).components(|c| {
+                *c = components;
+                c
+            })
+        })
+        .await?;
+
+    // 締め切ったときにボタンを消すため、募集のメッセージを覚えておく
+    // 投稿してからボタンでメンバーが変わっていることがあるので読みなおす
+    // 締め切っていたら何もしない
+    let _ = Lfg::update(&key, storage, |lfg| {
+        lfg.message_id = Some(message.id);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command
+        .name(COMMAND)
+        .description(format!(
+            "ゲームを一緒に遊ぶ人を募集します。持っている人だけが参加でき、{}分で締め切ります。",
+            LFG_TTL.as_secs() / 60
+        ))
+        .create_option(|option| {
+            option
+                .name("game")
+                .description("遊ぶゲーム")
+                .kind(CommandOptionType::String)
+                .required(true)
+                .set_autocomplete(true)
+        })
+        .create_option(|option| {
+            option
+                .name("slots")
+                .description("募集する人数 (自分は含まない)")
+                .kind(CommandOptionType::Integer)
+                .required(true)
+                .min_int_value(1)
+                .max_int_value(MAX_SLOTS)
+        })
+}
+
+/// `game` の補完
+pub async fn autocomplete(
+    ctx: impl AsRef<Http>,
+    autocomplete: &AutocompleteInteraction,
+    steam: &SteamApiClient,
+    steam_store: &StoreApiClient,
+    app_index: &AppIndex,
+    storage: &Storage,
+) -> Result<()> {
+    respond_game_choices(ctx, autocomplete, steam, steam_store, app_index, storage).await
+}
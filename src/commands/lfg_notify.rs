@@ -0,0 +1,63 @@
+use super::prelude::*;
+use crate::{
+    storage::{LoadError, Storage},
+    user::User,
+};
+
+pub const COMMAND: &str = "lfg-notify";
+
+pub async fn run(
+    ctx: impl AsRef<Http>,
+    command: &ApplicationCommandInteraction,
+    storage: &Storage,
+) -> Result<()> {
+    let enabled = command
+        .data
+        .options
+        .iter()
+        .find(|opt| opt.name == "enabled")
+        .and_then(|opt| opt.value.as_ref())
+        .and_then(|v| v.as_bool())
+        .unwrap_or(true);
+
+    let discord_id = command.user.id.to_string();
+    let content = match User::load(&discord_id, storage) {
+        Err(LoadError::Missing) => "先に `/register` で Steam ID を登録してください。",
+        Err(e @ LoadError::Corrupt(_)) => {
+            tracing::warn!("{e:?}");
+            "登録されている情報を読み込めませんでした。もう一度 `/register` してください。"
+        }
+        Ok(mut user) => {
+            user.set_lfg_notifications(enabled);
+            user.save(&discord_id, storage)?;
+            if enabled {
+                "持っているゲームの `/lfg` の募集があったら呼びます。"
+            } else {
+                "`/lfg` の募集で呼ばないようにしました。"
+            }
+        }
+    };
+
+    command
+        .create_interaction_response(ctx, |response| {
+            response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|msg| msg.ephemeral(true).content(content))
+        })
+        .await?;
+
+    Ok(())
+}
+
+pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command
+        .name(COMMAND)
+        .description("持っているゲームの `/lfg` の募集があったときに呼ばれるようにします。")
+        .create_option(|option| {
+            option
+                .name("enabled")
+                .description("呼ばれるかどうか")
+                .kind(CommandOptionType::Boolean)
+                .required(true)
+        })
+}
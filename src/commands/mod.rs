@@ -1,16 +1,236 @@
+pub mod achievements;
+pub mod common_wishlist;
+pub mod family;
+pub mod gamenight;
 pub mod get_common_games;
 pub mod help;
+pub mod hide_game;
+pub mod leaderboard;
+pub mod lfg;
+pub mod lfg_notify;
+pub mod near_misses;
+pub mod now_playing;
+pub mod recent;
 pub mod register;
+pub mod sale_alerts;
 pub mod show;
+pub mod unhide_game;
+pub mod who_owns;
 
-pub(self) mod prelude {
+use std::time::Duration;
+
+use serenity::model::id::UserId;
+
+use crate::{
+    app_index::{normalize, AppIndex},
+    steam::SteamApiClient,
+    storage::{LoadError, Storage},
+    store::StoreApiClient,
+    user::User,
+};
+
+/// 補完の候補の数の上限
+const MAX_CHOICES: usize = 25;
+
+/// 補完でストアの検索を待つ時間
+///
+/// 補完には 3 秒以内に答える必要がある
+const STORE_SEARCH_TIMEOUT: Duration = Duration::from_millis(1500);
+
+/// 補完で入力中のオプションの値
+fn focused_input(autocomplete: &prelude::AutocompleteInteraction) -> String {
+    autocomplete
+        .data
+        .options
+        .iter()
+        .find(|opt| opt.focused)
+        .and_then(|opt| opt.value.as_ref())
+        .and_then(|v| v.as_str())
+        .unwrap_or_default()
+        .to_lowercase()
+}
+
+/// 補完の選択肢の名前は 100 文字まで
+fn choice_name(name: &str) -> String {
+    name.chars().take(100).collect()
+}
+
+/// ユーザーのライブラリから名前に `input` を含むゲームを名前順に探す
+///
+/// 名前は [`normalize`] してから比べる
+///
+/// 未登録のユーザーのときは空になる
+async fn search_library(
+    steam: &SteamApiClient,
+    storage: &Storage,
+    user_id: UserId,
+    input: &str,
+) -> anyhow::Result<Vec<(u64, String)>> {
+    let input = normalize(input);
+    let Ok(user) = User::load(&user_id.to_string(), storage) else {
+        return Ok(Vec::new());
+    };
+    let mut games = steam
+        .get_owned_games(user.steam_id())
+        .await?
+        .into_values()
+        .map(|game| (game.game.appid, game.game.name))
+        .filter(|(_, name)| normalize(name).contains(&input))
+        .collect::<Vec<_>>();
+    games.sort_by(|a, b| a.1.cmp(&b.1));
+    games.truncate(MAX_CHOICES);
+    Ok(games)
+}
+
+/// ゲームを選ぶオプションの補完に答える
+///
+/// 自分のライブラリにあるゲームを先に、そのあとに Steam のすべてのアプリから近いものを出す。
+/// それでも足りなければストアで検索し、見つけた日本語の名前は索引に加える。
+/// 値には App ID を入れるので、[`resolve_game`] で読む
+async fn respond_game_choices(
+    ctx: impl AsRef<prelude::Http>,
+    autocomplete: &prelude::AutocompleteInteraction,
+    steam: &SteamApiClient,
+    steam_store: &StoreApiClient,
+    app_index: &AppIndex,
+    storage: &Storage,
+) -> anyhow::Result<()> {
+    let input = focused_input(autocomplete);
+    let mut games = search_library(steam, storage, autocomplete.user.id, &input).await?;
+    add_choices(&mut games, app_index.search(&input, MAX_CHOICES));
+    if games.len() < MAX_CHOICES && !input.trim().is_empty() {
+        match tokio::time::timeout(STORE_SEARCH_TIMEOUT, steam_store.search_apps(&input)).await {
+            Ok(Ok(apps)) => {
+                if let Err(e) = app_index.add_localized(&apps, storage) {
+                    tracing::warn!("{e:?}");
+                }
+                add_choices(
+                    &mut games,
+                    apps.into_iter().map(|app| (app.appid, app.name)).collect(),
+                );
+            }
+            Ok(Err(e)) => tracing::warn!("{e:?}"),
+            // 間に合わなければ、検索せずに答える
+            Err(_) => {}
+        }
+    }
+
+    autocomplete
+        .create_autocomplete_response(ctx, |response| {
+            for (appid, name) in games {
+                response.add_string_choice(choice_name(&name), appid);
+            }
+            response
+        })
+        .await?;
+    Ok(())
+}
+
+/// まだ入っていないゲームを上限まで加える
+fn add_choices(games: &mut Vec<(u64, String)>, candidates: Vec<(u64, String)>) {
+    for game in candidates {
+        if games.len() >= MAX_CHOICES {
+            break;
+        }
+        if !games.iter().any(|(appid, _)| *appid == game.0) {
+            games.push(game);
+        }
+    }
+}
+
+/// 入力から App ID と名前を引く
+///
+/// 補完から選ばれたときは App ID、そうでないときは名前が入力されている。
+/// 数字だけの名前のゲームもあるので、自分のライブラリかゲームの一覧にない数字は名前として探す
+async fn resolve_game(
+    input: &str,
+    user_id: UserId,
+    steam: &SteamApiClient,
+    steam_store: &StoreApiClient,
+    app_index: &AppIndex,
+    storage: &Storage,
+) -> anyhow::Result<Option<(u64, String)>> {
+    let library = match User::load(&user_id.to_string(), storage) {
+        Ok(user) => steam.get_owned_games(user.steam_id()).await?,
+        Err(LoadError::Missing) => Default::default(),
+        // 自分のライブラリが読めなくても、ゲームの一覧から引ける
+        Err(e @ LoadError::Corrupt(_)) => {
+            tracing::warn!("{e:?}");
+            Default::default()
+        }
+    };
+
+    let appid = input.parse::<u64>().ok();
+    if let Some(appid) = appid {
+        if let Some(game) = library.get(&appid) {
+            return Ok(Some((appid, game.game.name.clone())));
+        }
+        if let Some(name) = app_index.name(appid) {
+            return Ok(Some((appid, name)));
+        }
+    }
+
+    let normalized = normalize(input);
+    if let Some(game) = library
+        .into_values()
+        .find(|game| normalize(&game.game.name) == normalized)
+    {
+        return Ok(Some((game.game.appid, game.game.name)));
+    }
+    if let Some(game) = app_index
+        .search(&normalized, 1)
+        .into_iter()
+        .find(|(_, name)| normalize(name) == normalized)
+    {
+        return Ok(Some(game));
+    }
+
+    // 一覧をまだ読み込んでいないときは、補完から選ばれたものかもしれないのでストアで引く
+    let Some(appid) = appid.filter(|_| !app_index.is_loaded()) else {
+        return Ok(None);
+    };
+    Ok(steam_store
+        .get_app_details(appid)
+        .await?
+        .map(|details| (appid, details.name)))
+}
+
+/// 分で表したプレイ時間の表示
+fn playtime_text(minutes: u64) -> String {
+    match minutes {
+        0 => "未プレイ".to_string(),
+        1..=59 => format!("{minutes}分"),
+        _ => format!("{:.1}時間", minutes as f64 / 60.0),
+    }
+}
+
+/// 先に応答したあとで失敗したことを伝えて、エラーはそのまま返す
+///
+/// 考え中の表示のまま終わらないように、先に送った応答を書き換える
+async fn report_failure(
+    ctx: impl AsRef<prelude::Http>,
+    command: &prelude::ApplicationCommandInteraction,
+    e: anyhow::Error,
+) -> anyhow::Result<()> {
+    if let Err(edit) = command
+        .edit_original_interaction_response(ctx, |msg| {
+            msg.content("エラーが発生しました。しばらくしてからもう一度試してください。")
+        })
+        .await
+    {
+        tracing::warn!("{edit:?}");
+    }
+    Err(e)
+}
+
+mod prelude {
     pub use anyhow::Result;
     pub use serenity::{
-        builder::{CreateApplicationCommand, CreateApplicationCommandOption},
+        builder::CreateApplicationCommand,
         http::Http,
         model::prelude::{
-            application_command::ApplicationCommandInteraction, command::CommandOptionType, *,
+            application_command::ApplicationCommandInteraction,
+            autocomplete::AutocompleteInteraction, command::CommandOptionType, *,
         },
-        prelude::*,
     };
 }
@@ -0,0 +1,343 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use serenity::client::Cache;
+
+use super::prelude::*;
+use crate::{
+    common_games::{store_url, AppId, Member},
+    group::{load_libraries, near_misses, voice_members},
+    hidden::HiddenGames,
+    steam::{AppType, Library, SteamApiClient},
+    storage::Storage,
+    store::{AppDetails, Price, StoreApiClient, DEFAULT_COUNTRY},
+    user::User,
+};
+
+pub const COMMAND: &str = "near-misses";
+
+/// `max-missing` が指定されなかったときに許す持っていない人数
+const DEFAULT_MAX_MISSING: usize = 1;
+
+/// 指定できる持っていない人数の上限
+const MAX_MISSING: usize = 3;
+
+/// 価格で並べる候補の数の上限
+///
+/// 候補ごとにストアに問い合わせるので絞っておく。持っていない人数が少なく、よく遊ばれているものから選ぶ
+const MAX_CANDIDATES: usize = 40;
+
+/// 表示する数
+///
+/// 持っていない人の地域ごとの価格は、表示するものだけ調べる
+const MAX_ENTRIES: usize = 10;
+
+/// 持っていない人ひとりぶんの価格
+enum Cost {
+    Free,
+    Paid(Price),
+    /// ストアで買えない
+    Unavailable,
+}
+
+struct NearMiss {
+    appid: AppId,
+    name: String,
+    lacking: Vec<(Member, Cost)>,
+}
+
+impl NearMiss {
+    /// 通貨ごとの合計
+    fn totals(&self) -> BTreeMap<String, u64> {
+        let mut totals = BTreeMap::new();
+        for (_, cost) in &self.lacking {
+            if let Cost::Paid(price) = cost {
+                *totals.entry(price.currency.clone()).or_default() += price.final_;
+            }
+        }
+        totals
+    }
+
+    fn is_available(&self) -> bool {
+        self.lacking
+            .iter()
+            .all(|(_, cost)| !matches!(cost, Cost::Unavailable))
+    }
+}
+
+pub async fn run(
+    ctx: impl AsRef<Cache> + AsRef<Http>,
+    command: &ApplicationCommandInteraction,
+    steam: &SteamApiClient,
+    steam_store: &StoreApiClient,
+    storage: &Storage,
+) -> Result<()> {
+    let max_missing = command
+        .data
+        .options
+        .iter()
+        .find(|opt| opt.name == "max-missing")
+        .and_then(|opt| opt.value.as_ref())
+        .and_then(|v| v.as_u64())
+        .map(|v| v as usize)
+        .unwrap_or(DEFAULT_MAX_MISSING)
+        .clamp(1, MAX_MISSING);
+
+    let Some(guild) = command.guild_id.and_then(|id| id.to_guild_cached(&ctx)) else {
+        command
+            .create_interaction_response(&ctx, |resp| {
+                resp.kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|int| {
+                        int.ephemeral(true)
+                            .content("サーバーの内のチャンネルで呼び出してください。")
+                    })
+            })
+            .await?;
+        return Ok(());
+    };
+
+    let Some(channel_id) = guild
+        .voice_states
+        .get(&command.user.id)
+        .and_then(|s| s.channel_id)
+    else {
+        command
+            .create_interaction_response(&ctx, |resp| {
+                resp.kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|int| {
+                        int.ephemeral(true)
+                            .content("通話チャンネルにいる状態で呼び出してください。")
+                    })
+            })
+            .await?;
+        return Ok(());
+    };
+
+    // ライブラリやストアの情報の取得に時間がかかるので、先に応答しておく
+    command
+        .create_interaction_response(&ctx, |resp| {
+            resp.kind(InteractionResponseType::DeferredChannelMessageWithSource)
+                .interaction_response_data(|msg| msg.ephemeral(true))
+        })
+        .await?;
+
+    let (libraries, _) = load_libraries(voice_members(&guild, channel_id), steam, storage).await;
+    if libraries.len() < 2 {
+        command
+            .edit_original_interaction_response(&ctx, |msg| {
+                msg.content("ライブラリを読めたメンバーが2人以上いるときに使ってください。")
+            })
+            .await?;
+        return Ok(());
+    }
+
+    let hidden = HiddenGames::collect(
+        guild.id,
+        libraries.iter().map(|(member, _)| member.user_id),
+        storage,
+    );
+    // 種類がわかっているものは先に除いてから、候補を絞る
+    let candidates = near_misses(&libraries, &hidden, max_missing)
+        .into_iter()
+        .filter(|game| {
+            steam_store
+                .known_app_type(game.appid)
+                .is_none_or(|app_type| app_type == AppType::Game)
+        })
+        .take(MAX_CANDIDATES)
+        .collect::<Vec<_>>();
+
+    // 通貨がそろうように、並べるときはすべて日本の価格で比べる
+    // 日本のストアの情報から種類もわかるので、種類だけを別に問い合わせない
+    let base_details = steam_store
+        .get_app_details_many(candidates.iter().map(|game| game.appid).collect::<Vec<_>>())
+        .await;
+    let mut candidates = candidates
+        .into_iter()
+        .filter(|game| match base_details.get(&game.appid) {
+            Some(details) => AppType::from_store_type(&details.kind) == AppType::Game,
+            None => game.guess_app_type() == AppType::Game,
+        })
+        .map(|game| {
+            let lacking = libraries
+                .iter()
+                .filter(|(_, library)| !library.contains_key(&game.appid))
+                .count() as u64;
+            let price = match base_details.get(&game.appid) {
+                Some(AppDetails {
+                    price: Some(price), ..
+                }) => Some(price.final_),
+                Some(details) if details.is_free => Some(0),
+                _ => None,
+            };
+            (game, price.map(|price| price * lacking), lacking)
+        })
+        .collect::<Vec<_>>();
+    // 日本で買えないものは後ろに回す
+    candidates.sort_by_key(|(_, total, lacking)| (total.is_none(), *total, *lacking));
+    candidates.truncate(MAX_ENTRIES);
+
+    let countries = countries(&libraries, steam, storage).await;
+
+    // 持っていない人の地域ごとに価格を調べる
+    let mut lacking_by_country = HashMap::<&str, HashSet<AppId>>::new();
+    for (game, _, _) in &candidates {
+        for (member, library) in &libraries {
+            if !library.contains_key(&game.appid) {
+                lacking_by_country
+                    .entry(&countries[&member.user_id])
+                    .or_default()
+                    .insert(game.appid);
+            }
+        }
+    }
+    let mut details = HashMap::new();
+    for (country, appids) in lacking_by_country {
+        details.insert(
+            country,
+            steam_store.get_app_details_many_in(appids, country).await,
+        );
+    }
+
+    let entries = candidates
+        .into_iter()
+        .map(|(game, _, _)| {
+            let lacking = libraries
+                .iter()
+                .filter(|(_, library)| !library.contains_key(&game.appid))
+                .map(|(member, _)| {
+                    let country = countries[&member.user_id].as_str();
+                    let cost = match details.get(country).and_then(|d| d.get(&game.appid)) {
+                        Some(AppDetails {
+                            price: Some(price), ..
+                        }) => Cost::Paid(price.clone()),
+                        Some(details) if details.is_free => Cost::Free,
+                        _ => Cost::Unavailable,
+                    };
+                    (member.clone(), cost)
+                })
+                .collect();
+            NearMiss {
+                appid: game.appid,
+                name: game.name,
+                lacking,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let description = if entries.is_empty() {
+        format!("{max_missing}人以内が持っていないだけのゲームはありませんでした。")
+    } else {
+        entries.iter().map(render).collect::<Vec<_>>().join("\n\n")
+    };
+
+    command
+        .edit_original_interaction_response(&ctx, |msg| {
+            msg.embed(|embed| {
+                embed
+                    .title("あと少しで全員が持っているゲーム")
+                    .description(description)
+                    .footer(|f| {
+                        f.text(format!(
+                            "{}人中{max_missing}人以内が持っていないゲーム・日本のストアで全員が揃えるのに安い順",
+                            libraries.len()
+                        ))
+                    })
+            })
+        })
+        .await?;
+
+    Ok(())
+}
+
+pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command
+        .name(COMMAND)
+        .description(
+            "通話中のメンバーのうち少しの人だけが持っていないゲームを、揃えるのに安い順に表示します。",
+        )
+        .create_option(|option| {
+            option
+                .name("max-missing")
+                .description(format!(
+                    "持っていない人数の上限 (省略時は{DEFAULT_MAX_MISSING}人)"
+                ))
+                .kind(CommandOptionType::Integer)
+                .min_int_value(1)
+                .max_int_value(MAX_MISSING)
+        })
+}
+
+/// メンバーごとの価格を調べる地域
+///
+/// プロフィールで国を公開していないメンバーは日本の価格にする
+async fn countries(
+    libraries: &[(Member, Library)],
+    steam: &SteamApiClient,
+    storage: &Storage,
+) -> HashMap<UserId, String> {
+    let steam_ids = libraries
+        .iter()
+        .filter_map(|(member, _)| {
+            let user = User::load(&member.user_id.to_string(), storage).ok()?;
+            Some((member.user_id, user.steam_id().to_string()))
+        })
+        .collect::<Vec<_>>();
+    let summaries = steam
+        .get_player_summaries(
+            &steam_ids
+                .iter()
+                .map(|(_, id)| id.as_str())
+                .collect::<Vec<_>>(),
+        )
+        .await
+        .unwrap_or_else(|e| {
+            tracing::warn!("{e:?}");
+            Vec::new()
+        });
+
+    libraries
+        .iter()
+        .map(|(member, _)| {
+            let country = steam_ids
+                .iter()
+                .find(|(user_id, _)| *user_id == member.user_id)
+                .and_then(|(_, steam_id)| summaries.iter().find(|s| &s.steamid == steam_id))
+                .and_then(|s| s.loccountrycode.as_ref())
+                .map(|code| code.to_lowercase())
+                .unwrap_or_else(|| DEFAULT_COUNTRY.to_string());
+            (member.user_id, country)
+        })
+        .collect()
+}
+
+fn render(near_miss: &NearMiss) -> String {
+    let lacking = near_miss
+        .lacking
+        .iter()
+        .map(|(member, cost)| match cost {
+            Cost::Free => format!("{} (無料)", member.name),
+            Cost::Paid(price) => format!("{} ({})", member.name, price.text()),
+            Cost::Unavailable => format!("{} (購入できません)", member.name),
+        })
+        .collect::<Vec<_>>()
+        .join("、");
+    let total = if near_miss.is_available() {
+        let totals = near_miss.totals();
+        if totals.is_empty() {
+            "無料".to_string()
+        } else {
+            totals
+                .iter()
+                .map(|(currency, amount)| Price::format_amount(currency, *amount))
+                .collect::<Vec<_>>()
+                .join(" + ")
+        }
+    } else {
+        "購入できない人がいます".to_string()
+    };
+    format!(
+        "**[{}]({})**\n持っていない: {lacking}\n合計: {total}",
+        near_miss.name,
+        store_url(near_miss.appid)
+    )
+}
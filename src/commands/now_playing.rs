@@ -0,0 +1,200 @@
+use serenity::client::Cache;
+
+use super::prelude::*;
+use crate::{
+    app_index::{normalize, AppIndex},
+    common_games::{store_url, AppId, Member},
+    group::{load_libraries, load_users, voice_members},
+    steam::{PlayerSummary, SteamApiClient},
+    storage::Storage,
+};
+
+pub const COMMAND: &str = "now-playing";
+
+/// メンバーが遊んでいるゲーム
+struct Playing {
+    /// わかったときの App ID
+    appid: Option<AppId>,
+    name: String,
+    /// Steam のプロフィールから読めた
+    from_steam: bool,
+}
+
+pub async fn run(
+    ctx: impl AsRef<Cache> + AsRef<Http>,
+    command: &ApplicationCommandInteraction,
+    steam: &SteamApiClient,
+    app_index: &AppIndex,
+    storage: &Storage,
+) -> Result<()> {
+    let Some(guild) = command.guild_id.and_then(|id| id.to_guild_cached(&ctx)) else {
+        command
+            .create_interaction_response(&ctx, |resp| {
+                resp.kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|int| {
+                        int.ephemeral(true)
+                            .content("サーバーの内のチャンネルで呼び出してください。")
+                    })
+            })
+            .await?;
+        return Ok(());
+    };
+
+    let Some(channel_id) = guild
+        .voice_states
+        .get(&command.user.id)
+        .and_then(|s| s.channel_id)
+    else {
+        command
+            .create_interaction_response(&ctx, |resp| {
+                resp.kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|int| {
+                        int.ephemeral(true)
+                            .content("通話チャンネルにいる状態で呼び出してください。")
+                    })
+            })
+            .await?;
+        return Ok(());
+    };
+
+    // ライブラリの取得に時間がかかるので、先に応答しておく
+    command
+        .create_interaction_response(&ctx, |resp| {
+            resp.kind(InteractionResponseType::DeferredChannelMessageWithSource)
+                .interaction_response_data(|msg| msg.ephemeral(true))
+        })
+        .await?;
+
+    let members = voice_members(&guild, channel_id);
+    let users = load_users(members.clone(), storage);
+    let summaries = steam
+        .get_player_summaries(
+            &users
+                .iter()
+                .map(|(_, user)| user.steam_id())
+                .collect::<Vec<_>>(),
+        )
+        .await
+        .unwrap_or_else(|e| {
+            tracing::warn!("{e:?}");
+            Vec::new()
+        });
+
+    // Steam のプロフィールを優先し、なければ Discord のアクティビティを使う
+    let playing = members
+        .iter()
+        .map(|member| {
+            let summary = users
+                .iter()
+                .find(|(m, _)| m.user_id == member.user_id)
+                .and_then(|(_, user)| summaries.iter().find(|s| s.steamid == user.steam_id()));
+            let playing = summary.and_then(steam_playing).or_else(|| {
+                let presence = guild.presences.get(&member.user_id)?;
+                let activity = presence
+                    .activities
+                    .iter()
+                    .find(|activity| activity.kind == ActivityType::Playing)?;
+                // 名前が一致するアプリがあれば Steam のゲームとみなす
+                let input = normalize(&activity.name);
+                let appid = app_index
+                    .search(&activity.name, 1)
+                    .into_iter()
+                    .find(|(_, name)| normalize(name) == input)
+                    .map(|(appid, _)| appid);
+                Some(Playing {
+                    appid,
+                    name: activity.name.clone(),
+                    from_steam: false,
+                })
+            });
+            (member, playing)
+        })
+        .collect::<Vec<_>>();
+
+    // 誰かが遊んでいるゲームのうち、ライブラリを読めた全員が持っているもの
+    let (libraries, _) = load_libraries(members.clone(), steam, storage).await;
+    let mut joinable = playing
+        .iter()
+        .filter_map(|(_, playing)| {
+            let playing = playing.as_ref()?;
+            let appid = playing.appid?;
+            libraries
+                .iter()
+                .all(|(_, library)| library.contains_key(&appid))
+                .then(|| (appid, playing.name.clone()))
+        })
+        .collect::<Vec<_>>();
+    joinable.sort();
+    joinable.dedup_by_key(|(appid, _)| *appid);
+
+    let lines = playing
+        .iter()
+        .map(|(member, playing)| render(member, playing.as_ref()))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let joinable_text = if libraries.len() < 2 {
+        "ライブラリを読めたメンバーが2人以上いるときに表示します。".to_string()
+    } else if joinable.is_empty() {
+        "全員が持っているゲームを遊んでいる人はいません。".to_string()
+    } else {
+        joinable
+            .iter()
+            .map(|(appid, name)| format!("- [{name}]({})", store_url(*appid)))
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+
+    command
+        .edit_original_interaction_response(&ctx, |msg| {
+            msg.embed(|embed| {
+                embed
+                    .title("通話中のメンバーが遊んでいるゲーム")
+                    .description(lines)
+                    .field("みんなで合流できるゲーム", joinable_text, false)
+                    .footer(|f| {
+                        f.text("Steam のプロフィールか Discord のアクティビティから調べています")
+                    })
+            })
+        })
+        .await?;
+
+    Ok(())
+}
+
+pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command.name(COMMAND).description(
+        "通話中のメンバーがいま遊んでいるゲームと、全員で合流できるゲームを表示します。",
+    )
+}
+
+/// Steam のプロフィールで公開されている、遊んでいる最中のゲーム
+fn steam_playing(summary: &PlayerSummary) -> Option<Playing> {
+    let name = summary.gameextrainfo.clone()?;
+    Some(Playing {
+        appid: summary.gameid.as_ref().and_then(|id| id.parse().ok()),
+        name,
+        from_steam: true,
+    })
+}
+
+fn render(member: &Member, playing: Option<&Playing>) -> String {
+    match playing {
+        None => format!("{} — 遊んでいません", member.name),
+        Some(playing) => {
+            let source = if playing.from_steam {
+                "Steam"
+            } else {
+                "Discord"
+            };
+            match playing.appid {
+                Some(appid) => format!(
+                    "{} — [{}]({}) ({source})",
+                    member.name,
+                    playing.name,
+                    store_url(appid)
+                ),
+                None => format!("{} — {} ({source})", member.name, playing.name),
+            }
+        }
+    }
+}
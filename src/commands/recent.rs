@@ -0,0 +1,293 @@
+use std::collections::HashMap;
+
+use serenity::client::Cache;
+
+use super::{playtime_text, prelude::*};
+use crate::{
+    common_games::{store_url, AppId, Member},
+    group::{load_libraries, load_recent_games, voice_members},
+    hidden::HiddenGames,
+    library_sync::{NewGame, StoredLibrary},
+    steam::{AppType, Game, SteamApiClient},
+    storage::{LoadError, Storage},
+    store::StoreApiClient,
+};
+
+pub const COMMAND: &str = "recent";
+
+/// `min-players` が指定されなかったときの、遊んだ人数の下限
+const DEFAULT_MIN_PLAYERS: usize = 2;
+
+/// 表示する数
+const MAX_ENTRIES: usize = 15;
+
+/// 最近買ったゲームを表示する数
+const MAX_NEW_GAMES: usize = 10;
+
+/// 埋め込みのフィールドの文字数の上限
+const MAX_FIELD_LENGTH: usize = 1024;
+
+/// 最近みんなで遊んでいるゲーム
+struct RecentGame {
+    game: Game,
+    /// 遊んだメンバーと直近 2 週間のプレイ時間 (分)
+    players: Vec<(Member, u64)>,
+    /// 通話にいるが持っていないメンバー
+    lacking: Vec<Member>,
+}
+
+impl RecentGame {
+    fn total_playtime(&self) -> u64 {
+        self.players.iter().map(|(_, playtime)| playtime).sum()
+    }
+}
+
+pub async fn run(
+    ctx: impl AsRef<Cache> + AsRef<Http>,
+    command: &ApplicationCommandInteraction,
+    steam: &SteamApiClient,
+    steam_store: &StoreApiClient,
+    storage: &Storage,
+) -> Result<()> {
+    let min_players = command
+        .data
+        .options
+        .iter()
+        .find(|opt| opt.name == "min-players")
+        .and_then(|opt| opt.value.as_ref())
+        .and_then(|v| v.as_u64())
+        .map(|v| v as usize)
+        .unwrap_or(DEFAULT_MIN_PLAYERS)
+        .max(1);
+
+    let Some(guild) = command.guild_id.and_then(|id| id.to_guild_cached(&ctx)) else {
+        command
+            .create_interaction_response(&ctx, |resp| {
+                resp.kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|int| {
+                        int.ephemeral(true)
+                            .content("サーバーの内のチャンネルで呼び出してください。")
+                    })
+            })
+            .await?;
+        return Ok(());
+    };
+
+    let Some(channel_id) = guild
+        .voice_states
+        .get(&command.user.id)
+        .and_then(|s| s.channel_id)
+    else {
+        command
+            .create_interaction_response(&ctx, |resp| {
+                resp.kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|int| {
+                        int.ephemeral(true)
+                            .content("通話チャンネルにいる状態で呼び出してください。")
+                    })
+            })
+            .await?;
+        return Ok(());
+    };
+
+    // メンバーごとに問い合わせるので、先に応答しておく
+    command
+        .create_interaction_response(&ctx, |resp| {
+            resp.kind(InteractionResponseType::DeferredChannelMessageWithSource)
+                .interaction_response_data(|msg| msg.ephemeral(true))
+        })
+        .await?;
+
+    let members = voice_members(&guild, channel_id);
+    let purchases = recent_purchases(&members, storage);
+    let (recent, failed) = load_recent_games(members.clone(), steam, storage).await;
+    // 遊んでいない人が持っているかを調べる。ライブラリはキャッシュされている
+    let (libraries, _) = load_libraries(members, steam, storage).await;
+    let hidden = HiddenGames::collect(
+        guild.id,
+        recent.iter().map(|(member, _)| member.user_id),
+        storage,
+    );
+
+    let mut games = HashMap::<AppId, RecentGame>::new();
+    for (member, played) in &recent {
+        for game in played {
+            if hidden.contains(&game.game.appid) {
+                continue;
+            }
+            games
+                .entry(game.game.appid)
+                .or_insert_with(|| RecentGame {
+                    game: game.game.clone(),
+                    players: Vec::new(),
+                    lacking: Vec::new(),
+                })
+                .players
+                .push((member.clone(), game.playtime_2weeks));
+        }
+    }
+    let candidates = games
+        .values()
+        .filter(|entry| entry.players.len() >= min_players)
+        .map(|entry| entry.game.clone())
+        .collect::<Vec<_>>();
+    let types = steam_store.get_app_types(&candidates).await;
+    let mut entries = games
+        .into_values()
+        .filter(|entry| entry.players.len() >= min_players)
+        .filter(|entry| types.get(&entry.game.appid) == Some(&AppType::Game))
+        .map(|mut entry| {
+            entry
+                .players
+                .sort_by_key(|(_, playtime)| std::cmp::Reverse(*playtime));
+            entry.lacking = libraries
+                .iter()
+                .filter(|(_, library)| !library.contains_key(&entry.game.appid))
+                .map(|(member, _)| member.clone())
+                .collect();
+            entry
+        })
+        .collect::<Vec<_>>();
+    // 遊んだ人が多く、長く遊ばれているものを先にする
+    entries.sort_by(|a, b| {
+        b.players
+            .len()
+            .cmp(&a.players.len())
+            .then(b.total_playtime().cmp(&a.total_playtime()))
+            .then(a.game.appid.cmp(&b.game.appid))
+    });
+    entries.truncate(MAX_ENTRIES);
+
+    let description = if entries.is_empty() {
+        format!("直近2週間に{min_players}人以上が遊んだゲームはありませんでした。")
+    } else {
+        entries.iter().map(render).collect::<Vec<_>>().join("\n\n")
+    };
+    let purchases = purchases
+        .into_iter()
+        .filter(|(game, _)| !hidden.contains(&game.appid))
+        .take(MAX_NEW_GAMES)
+        .map(|(game, buyers)| render_purchase(&game, &buyers))
+        .fold(String::new(), |mut text, line| {
+            // 入りきらないものは省く
+            if text.chars().count() + line.chars().count() < MAX_FIELD_LENGTH {
+                text.push_str(&line);
+                text.push('\n');
+            }
+            text
+        });
+    let mut footer = format!(
+        "{}人の最近遊んだゲームを読みました・{min_players}人以上が遊んだゲーム",
+        recent.len()
+    );
+    if !failed.is_empty() {
+        footer.push_str(&format!("・{}人は読めませんでした", failed.len()));
+    }
+
+    command
+        .edit_original_interaction_response(&ctx, |msg| {
+            msg.embed(|embed| {
+                embed
+                    .title("最近みんなで遊んでいるゲーム")
+                    .description(description)
+                    .footer(|f| f.text(footer));
+                if !purchases.is_empty() {
+                    embed.field("最近買ったゲーム", purchases, false);
+                }
+                embed
+            })
+        })
+        .await?;
+
+    Ok(())
+}
+
+pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command
+        .name(COMMAND)
+        .description(
+            "通話中のメンバーが直近2週間に遊んだゲームのうち何人もが遊んでいるものと、最近買ったゲームを表示します。",
+        )
+        .create_option(|option| {
+            option
+                .name("min-players")
+                .description(format!(
+                    "遊んだ人数の下限 (省略時は{DEFAULT_MIN_PLAYERS}人)"
+                ))
+                .kind(CommandOptionType::Integer)
+                .min_int_value(1)
+        })
+}
+
+fn render(entry: &RecentGame) -> String {
+    let players = entry
+        .players
+        .iter()
+        .map(|(member, playtime)| format!("{} ({})", member.name, playtime_text(*playtime)))
+        .collect::<Vec<_>>()
+        .join("、");
+    let mut text = format!(
+        "**[{}]({})**\n遊んだ: {players}",
+        entry.game.name,
+        store_url(entry.game.appid)
+    );
+    if !entry.lacking.is_empty() {
+        let lacking = entry
+            .lacking
+            .iter()
+            .map(|member| member.name.as_str())
+            .collect::<Vec<_>>()
+            .join("、");
+        text.push_str(&format!("\n持っていない: {lacking}"));
+    }
+    text
+}
+
+/// メンバーが直近 30 日に買ったゲームと買ったメンバー
+///
+/// 保存しているライブラリの記録から読むので、ライブラリを取得しなおすまでは出てこない。
+/// 新しく買ったものから並べる
+fn recent_purchases(members: &[Member], storage: &Storage) -> Vec<(NewGame, Vec<Member>)> {
+    let mut purchases = HashMap::<AppId, (NewGame, Vec<Member>)>::new();
+    for member in members {
+        let stored = match StoredLibrary::load(&member.user_id.to_string(), storage) {
+            Ok(stored) => stored,
+            Err(LoadError::Missing) => continue,
+            Err(e @ LoadError::Corrupt(_)) => {
+                tracing::warn!("{e:?}");
+                continue;
+            }
+        };
+        for game in stored.new_games() {
+            let (latest, buyers) = purchases
+                .entry(game.appid)
+                .or_insert_with(|| (game.clone(), Vec::new()));
+            if game.found_at.unix_timestamp() > latest.found_at.unix_timestamp() {
+                *latest = game.clone();
+            }
+            buyers.push(member.clone());
+        }
+    }
+    let mut purchases = purchases.into_values().collect::<Vec<_>>();
+    purchases.sort_by(|(a, _), (b, _)| {
+        b.found_at
+            .unix_timestamp()
+            .cmp(&a.found_at.unix_timestamp())
+            .then(a.appid.cmp(&b.appid))
+    });
+    purchases
+}
+
+fn render_purchase(game: &NewGame, buyers: &[Member]) -> String {
+    let buyers = buyers
+        .iter()
+        .map(|member| member.name.as_str())
+        .collect::<Vec<_>>()
+        .join("、");
+    format!(
+        "[{}]({}) — {buyers} (<t:{}:R>)",
+        game.name,
+        store_url(game.appid),
+        game.found_at.unix_timestamp()
+    )
+}
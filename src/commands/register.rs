@@ -1,15 +1,14 @@
 use anyhow::bail;
-use shuttle_persist::PersistInstance;
 
 use super::prelude::*;
-use crate::user::User;
+use crate::{storage::Storage, user::User};
 
 pub const COMMAND: &str = "register";
 
 pub async fn run(
     ctx: impl AsRef<Http>,
     command: &ApplicationCommandInteraction,
-    persist: &PersistInstance,
+    storage: &Storage,
 ) -> Result<()> {
     let Some(steam_id) = command.data.options
         .iter()
@@ -19,8 +18,13 @@ pub async fn run(
             bail!("steam id is missing.");
         };
 
-    let user = User::new(steam_id.to_string());
-    if let Err(e) = user.save(&command.user.id.to_string(), persist) {
+    // 登録しなおすときもファミリーと募集の通知の設定は引き継ぐ
+    let mut user = User::new(steam_id.to_string());
+    if let Ok(old) = User::load(&command.user.id.to_string(), storage) {
+        user.set_family(old.family().map(ToString::to_string));
+        user.set_lfg_notifications(old.lfg_notifications());
+    }
+    if let Err(e) = user.save(&command.user.id.to_string(), storage) {
         bail!("Insert user error. {e:?}");
     }
 
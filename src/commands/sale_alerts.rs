@@ -0,0 +1,65 @@
+use super::prelude::*;
+use crate::{sale_alerts::SaleAlerts, storage::Storage};
+
+pub const COMMAND: &str = "sale-alerts";
+
+pub async fn run(
+    ctx: impl AsRef<Http>,
+    command: &ApplicationCommandInteraction,
+    storage: &Storage,
+) -> Result<()> {
+    let enabled = command
+        .data
+        .options
+        .iter()
+        .find(|opt| opt.name == "enabled")
+        .and_then(|opt| opt.value.as_ref())
+        .and_then(|v| v.as_bool())
+        .unwrap_or(true);
+    let can_manage = command
+        .member
+        .as_ref()
+        .and_then(|m| m.permissions)
+        .is_some_and(|p| p.manage_guild());
+
+    let content = match command.guild_id {
+        None => "サーバーの内のチャンネルで呼び出してください。".to_string(),
+        Some(_) if !can_manage => {
+            "セールの通知を設定するにはサーバーの管理権限が必要です。".to_string()
+        }
+        Some(guild_id) if enabled => {
+            SaleAlerts::subscribe(guild_id, command.channel_id, storage)?;
+            format!(
+                "<#{}> にみんなが欲しがっているゲームのセールを通知します。",
+                command.channel_id
+            )
+        }
+        Some(guild_id) => {
+            SaleAlerts::remove(guild_id, storage)?;
+            "セールの通知をやめました。".to_string()
+        }
+    };
+
+    command
+        .create_interaction_response(ctx, |response| {
+            response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|msg| msg.ephemeral(true).content(content))
+        })
+        .await?;
+
+    Ok(())
+}
+
+pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command
+        .name(COMMAND)
+        .description("共通のウィッシュリストやあと少しで揃うゲームがセールになったら、このチャンネルに通知します。")
+        .create_option(|option| {
+            option
+                .name("enabled")
+                .description("通知するかどうか")
+                .kind(CommandOptionType::Boolean)
+                .required(true)
+        })
+}
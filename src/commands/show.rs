@@ -1,22 +1,28 @@
-use shuttle_persist::PersistInstance;
-
 use super::prelude::*;
-use crate::user::User;
+use crate::{
+    storage::{LoadError, Storage},
+    user::User,
+};
 
 pub const COMMAND: &str = "show";
 
 pub async fn run(
     ctx: impl AsRef<Http>,
     command: &ApplicationCommandInteraction,
-    persist: &PersistInstance,
+    storage: &Storage,
 ) -> Result<()> {
-    let content = match User::load(&command.user.id.to_string(), persist) {
+    let content = match User::load(&command.user.id.to_string(), storage) {
         Ok(user) => format!(
             "あなたのSteamIDは[{}](https://steamcommunity.com/profiles/{})として登録されています。",
             user.steam_id(),
             user.steam_id(),
         ),
-        Err(_e) => "あなたのSteamIDは未登録のようです。".to_string(),
+        Err(LoadError::Missing) => "あなたのSteamIDは未登録のようです。".to_string(),
+        Err(e @ LoadError::Corrupt(_)) => {
+            tracing::warn!("{e:?}");
+            "登録されている情報を読み込めませんでした。もう一度 `/register` してください。"
+                .to_string()
+        }
     };
 
     command
@@ -0,0 +1,117 @@
+use super::{
+    choice_name, focused_input,
+    hide_game::{parse_scope, scope_option},
+    prelude::*,
+    MAX_CHOICES,
+};
+use crate::{
+    hidden::{HiddenGames, HideScope},
+    storage::Storage,
+};
+
+pub const COMMAND: &str = "unhide-game";
+
+pub async fn run(
+    ctx: impl AsRef<Http>,
+    command: &ApplicationCommandInteraction,
+    storage: &Storage,
+) -> Result<()> {
+    let input = command
+        .data
+        .options
+        .iter()
+        .find(|opt| opt.name == "game")
+        .and_then(|opt| opt.value.as_ref())
+        .and_then(|v| v.as_str())
+        .unwrap_or_default()
+        .trim()
+        .to_string();
+
+    let content = match parse_scope(command) {
+        Err(notice) => notice.to_string(),
+        Ok(scope) => {
+            let mut hidden = HiddenGames::load(scope, storage)?;
+            // 補完から選ばれたときは App ID、そうでないときは名前が入力されている
+            let appid = input.parse::<u64>().ok().or_else(|| {
+                let input = input.to_lowercase();
+                hidden
+                    .iter()
+                    .find(|(_, name)| name.to_lowercase() == input)
+                    .map(|(appid, _)| appid)
+            });
+            match appid.and_then(|appid| hidden.remove(appid)) {
+                Some(name) => {
+                    hidden.save(scope, storage)?;
+                    format!("{name} をふたたび一覧に表示するようにしました。")
+                }
+                None => format!("「{input}」は非表示にしていません。"),
+            }
+        }
+    };
+
+    command
+        .create_interaction_response(ctx, |response| {
+            response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|msg| msg.ephemeral(true).content(content))
+        })
+        .await?;
+
+    Ok(())
+}
+
+pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command
+        .name(COMMAND)
+        .description("非表示にしたゲームを共通のゲームの一覧に表示するように戻します。")
+        .create_option(|option| {
+            option
+                .name("game")
+                .description("表示に戻すゲーム")
+                .kind(CommandOptionType::String)
+                .required(true)
+                .set_autocomplete(true)
+        })
+        .create_option(scope_option)
+}
+
+/// `game` の補完
+///
+/// 選んでいる `scope` のリストにあるゲームを候補にする
+pub async fn autocomplete(
+    ctx: impl AsRef<Http>,
+    autocomplete: &AutocompleteInteraction,
+    storage: &Storage,
+) -> Result<()> {
+    let input = focused_input(autocomplete);
+    let guild = autocomplete
+        .data
+        .options
+        .iter()
+        .find(|opt| opt.name == "scope")
+        .and_then(|opt| opt.value.as_ref())
+        .and_then(|v| v.as_str())
+        == Some("guild");
+    let scope = match autocomplete.guild_id {
+        Some(guild_id) if guild => HideScope::Guild(guild_id),
+        _ => HideScope::User(autocomplete.user.id),
+    };
+
+    let hidden = HiddenGames::load(scope, storage)?;
+    let mut games = hidden
+        .iter()
+        .filter(|(_, name)| name.to_lowercase().contains(&input))
+        .collect::<Vec<_>>();
+    games.sort_by_key(|(_, name)| *name);
+    games.truncate(MAX_CHOICES);
+
+    autocomplete
+        .create_autocomplete_response(ctx, |response| {
+            for (appid, name) in games {
+                response.add_string_choice(choice_name(name), appid);
+            }
+            response
+        })
+        .await?;
+    Ok(())
+}
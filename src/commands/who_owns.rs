@@ -0,0 +1,225 @@
+use serenity::client::Context;
+
+use super::{playtime_text, prelude::*, report_failure, resolve_game, respond_game_choices};
+use crate::{
+    app_index::AppIndex,
+    common_games::store_url,
+    group::{load_libraries, registered_members, voice_members},
+    steam::SteamApiClient,
+    storage::Storage,
+    store::StoreApiClient,
+};
+
+pub const COMMAND: &str = "who-owns";
+
+/// 埋め込みのフィールドの値の長さの上限
+const MAX_FIELD_LENGTH: usize = 1024;
+
+pub async fn run(
+    ctx: &Context,
+    command: &ApplicationCommandInteraction,
+    steam: &SteamApiClient,
+    steam_store: &StoreApiClient,
+    app_index: &AppIndex,
+    storage: &Storage,
+) -> Result<()> {
+    let input = command
+        .data
+        .options
+        .iter()
+        .find(|opt| opt.name == "game")
+        .and_then(|opt| opt.value.as_ref())
+        .and_then(|v| v.as_str())
+        .unwrap_or_default()
+        .trim()
+        .to_string();
+    let voice_only = command
+        .data
+        .options
+        .iter()
+        .find(|opt| opt.name == "scope")
+        .and_then(|opt| opt.value.as_ref())
+        .and_then(|v| v.as_str())
+        == Some("voice");
+
+    let Some(guild) = command.guild_id.and_then(|id| id.to_guild_cached(ctx)) else {
+        command
+            .create_interaction_response(ctx, |resp| {
+                resp.kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|int| {
+                        int.ephemeral(true)
+                            .content("サーバーの内のチャンネルで呼び出してください。")
+                    })
+            })
+            .await?;
+        return Ok(());
+    };
+
+    let channel_id = guild
+        .voice_states
+        .get(&command.user.id)
+        .and_then(|s| s.channel_id);
+    if voice_only && channel_id.is_none() {
+        command
+            .create_interaction_response(ctx, |resp| {
+                resp.kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|int| {
+                        int.ephemeral(true)
+                            .content("通話チャンネルにいる状態で呼び出してください。")
+                    })
+            })
+            .await?;
+        return Ok(());
+    }
+
+    // ライブラリの取得に時間がかかるので、先に応答しておく
+    command
+        .create_interaction_response(ctx, |resp| {
+            resp.kind(InteractionResponseType::DeferredChannelMessageWithSource)
+                .interaction_response_data(|msg| msg.ephemeral(true))
+        })
+        .await?;
+
+    // 先に応答したので、失敗しても考え中の表示のままにしない
+    let result = async {
+        let Some((appid, name)) = resolve_game(
+            &input,
+            command.user.id,
+            steam,
+            steam_store,
+            app_index,
+            storage,
+        )
+        .await?
+        else {
+            command
+                .edit_original_interaction_response(ctx, |msg| {
+                    msg.content(format!(
+                        "「{input}」というゲームが見つかりませんでした。候補から選んでください。"
+                    ))
+                })
+                .await?;
+            return Ok(());
+        };
+
+        let members = match channel_id {
+            Some(channel_id) if voice_only => voice_members(&guild, channel_id),
+            _ => registered_members(ctx, &guild, storage).await?,
+        };
+        let (libraries, failed) = load_libraries(members, steam, storage).await;
+
+        let mut owners = Vec::new();
+        let mut non_owners = Vec::new();
+        for (member, library) in libraries {
+            match library.get(&appid) {
+                Some(game) => owners.push((member, game.playtime_forever)),
+                None => non_owners.push(member),
+            }
+        }
+        // よく遊んでいる人を先にする
+        owners.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.name.cmp(&b.0.name)));
+        non_owners.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let owner_lines = owners
+            .iter()
+            .map(|(member, playtime)| format!("{} — {}", member.name, playtime_text(*playtime)))
+            .collect::<Vec<_>>();
+        let non_owner_lines = non_owners
+            .iter()
+            .map(|member| member.name.clone())
+            .collect::<Vec<_>>();
+        let failed_lines = failed
+            .iter()
+            .map(|member| member.name.clone())
+            .collect::<Vec<_>>();
+        let target = if voice_only {
+            "通話中のメンバー"
+        } else {
+            "Steam IDを登録しているサーバーのメンバー"
+        };
+
+        command
+            .edit_original_interaction_response(ctx, |msg| {
+                msg.embed(|embed| {
+                    embed
+                        .title(&name)
+                        .url(store_url(appid))
+                        .description(format!("{target}のうち{}人が持っています。", owners.len()))
+                        .field(
+                            format!("持っている ({}人)", owner_lines.len()),
+                            list_field(&owner_lines),
+                            false,
+                        )
+                        .field(
+                            format!("持っていない ({}人)", non_owner_lines.len()),
+                            list_field(&non_owner_lines),
+                            false,
+                        );
+                    if !failed_lines.is_empty() {
+                        embed.field("ライブラリを読めなかった", list_field(&failed_lines), false);
+                    }
+                    embed
+                })
+            })
+            .await?;
+
+        anyhow::Ok(())
+    }
+    .await;
+    if let Err(e) = result {
+        return report_failure(ctx, command, e).await;
+    }
+    Ok(())
+}
+
+pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command
+        .name(COMMAND)
+        .description("サーバーのメンバーのうち、だれがゲームを持っているかを表示します。")
+        .create_option(|option| {
+            option
+                .name("game")
+                .description("調べるゲーム")
+                .kind(CommandOptionType::String)
+                .required(true)
+                .set_autocomplete(true)
+        })
+        .create_option(|option| {
+            option
+                .name("scope")
+                .description("調べるメンバー (省略時はサーバー全体)")
+                .kind(CommandOptionType::String)
+                .add_string_choice("サーバー全体", "guild")
+                .add_string_choice("通話中のメンバー", "voice")
+        })
+}
+
+/// `game` の補完
+pub async fn autocomplete(
+    ctx: impl AsRef<Http>,
+    autocomplete: &AutocompleteInteraction,
+    steam: &SteamApiClient,
+    steam_store: &StoreApiClient,
+    app_index: &AppIndex,
+    storage: &Storage,
+) -> Result<()> {
+    respond_game_choices(ctx, autocomplete, steam, steam_store, app_index, storage).await
+}
+
+/// 1 行ずつ並べ、入りきらなければ残りの人数を書く
+fn list_field(lines: &[String]) -> String {
+    if lines.is_empty() {
+        return "なし".to_string();
+    }
+    let mut text = String::new();
+    for (i, line) in lines.iter().enumerate() {
+        let rest = format!("ほか{}人", lines.len() - i);
+        if text.len() + line.len() + rest.len() + 2 > MAX_FIELD_LENGTH {
+            text.push_str(&rest);
+            break;
+        }
+        text.push_str(line);
+        text.push('\n');
+    }
+    text
+}
@@ -1,45 +1,467 @@
 use std::{
     collections::{HashMap, HashSet},
+    fmt,
     str::FromStr,
+    time::Duration,
 };
 
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use serenity::builder::CreateInteractionResponseData;
-use shuttle_persist::PersistInstance;
+use serenity::{
+    builder::{CreateComponents, CreateEmbed},
+    http::Http,
+    model::{
+        application::{
+            component::{ActionRow, ActionRowComponent, ButtonStyle, InputTextStyle},
+            interaction::{
+                message_component::MessageComponentInteraction, modal::ModalSubmitInteraction,
+                InteractionResponseType,
+            },
+        },
+        channel::AttachmentType,
+        id::{InteractionId, UserId},
+        Timestamp,
+    },
+};
 
-use crate::steam::Game;
+use crate::{
+    steam::{Game, Library, SteamApiClient},
+    storage::{LoadError, Record, Storage},
+    store::{AppDetails, StoreApiClient},
+    user::User,
+};
 
 pub type AppId = u64;
 
-pub const PAGE_SIZE: usize = 10;
+/// ページの大きさを指定しなかったときの 1 ページあたりのゲームの数
+pub const DEFAULT_PAGE_SIZE: usize = 10;
+
+/// 1 ページあたりのゲームの数の上限
+/// 一覧は 1 つの埋め込みの説明 (4096 文字まで) に並べるので、名前が長くても収まる数にしておく
+pub const MAX_PAGE_SIZE: usize = 25;
+
+/// 詳細を表示するときの 1 ページあたりのゲームの数の上限
+/// ゲームごとに埋め込みを作るので、1 つのメッセージにつけられる埋め込みの数に合わせる
+pub const MAX_DETAILED_PAGE_SIZE: usize = 10;
+
+/// ジャンルや価格で絞り込むときに、キャッシュしていないストアの情報を問い合わせる数の上限
+/// ストアは 5 分に 200 件ほどしか答えないので、ほかの機能の分を残しておく
+const MAX_FILTER_LOOKUPS: usize = 100;
+
+/// 一覧の表示のしかた
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Debug)]
+pub struct Layout {
+    pub page_size: usize,
+    /// ストアの情報を使ってゲームごとに詳しく表示する
+    pub detailed: bool,
+}
+
+impl Layout {
+    /// ページの大きさは表示のしかたに合わせて上限と下限に丸める
+    pub fn new(page_size: Option<usize>, detailed: bool) -> Layout {
+        let max = if detailed {
+            MAX_DETAILED_PAGE_SIZE
+        } else {
+            MAX_PAGE_SIZE
+        };
+        let page_size = page_size.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, max);
+        Layout {
+            page_size,
+            detailed,
+        }
+    }
+}
+
+/// 一覧の絞り込みの条件
+#[derive(Serialize, Deserialize, Default, PartialEq, Eq, Clone, Debug)]
+pub struct Filter {
+    /// 名前に含まれる文字列
+    ///
+    /// 絞り込んだ一覧をさらに絞り込むと増えるので、すべて含まれるものを選ぶ
+    pub queries: Vec<String>,
+    /// ストアのジャンルかカテゴリに含まれる文字列
+    pub genres: Vec<String>,
+    /// 現在の価格の上限 (円)
+    pub max_price: Option<u64>,
+}
+
+impl Filter {
+    /// 名前で絞り込む
+    pub fn by_query(query: String) -> Filter {
+        Filter {
+            queries: vec![query],
+            ..Default::default()
+        }
+    }
+
+    /// 絞り込みのモーダルの入力から条件を作る
+    fn from_modal(rows: &[ActionRow]) -> Filter {
+        let mut filter = Filter::default();
+        for component in rows.iter().flat_map(|row| &row.components) {
+            let ActionRowComponent::InputText(input) = component else {
+                continue;
+            };
+            let value = input.value.trim();
+            if value.is_empty() {
+                continue;
+            }
+            match input.custom_id.as_str() {
+                "query" => filter.queries.push(value.to_string()),
+                "genre" => filter.genres.push(value.to_string()),
+                // `1,980円` のような入力も受けつける
+                "max-price" => {
+                    filter.max_price = value
+                        .chars()
+                        .filter(char::is_ascii_digit)
+                        .collect::<String>()
+                        .parse()
+                        .ok()
+                }
+                _ => {}
+            }
+        }
+        filter
+    }
+
+    /// 絞り込んだ一覧をさらに `other` で絞り込むときの条件
+    fn and(mut self, other: Filter) -> Filter {
+        self.queries.extend(other.queries);
+        self.genres.extend(other.genres);
+        self.max_price = match (self.max_price, other.max_price) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        self
+    }
+
+    /// 絞り込むのにストアの情報が必要か
+    fn needs_details(&self) -> bool {
+        !self.genres.is_empty() || self.max_price.is_some()
+    }
+
+    /// 名前の条件に合うか
+    ///
+    /// ストアの情報を取得する前に、名前だけで外れるものを除くのに使う
+    fn matches_name(&self, game: &Game) -> bool {
+        self.queries.iter().all(|query| contains(&game.name, query))
+    }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+    fn matches(&self, game: &Game, details: Option<&AppDetails>) -> bool {
+        if !self.matches_name(game) {
+            return false;
+        }
+        if !self.needs_details() {
+            return true;
+        }
+        let Some(details) = details else {
+            return false;
+        };
+        for genre in &self.genres {
+            let mut names = details
+                .genres
+                .iter()
+                .chain(details.categories.iter().map(|c| &c.description));
+            if !names.any(|name| contains(name, genre)) {
+                return false;
+            }
+        }
+        if let Some(max_price) = self.max_price {
+            let price = details.price.as_ref().map(|p| p.final_ / 100);
+            match price {
+                Some(price) if price > max_price => return false,
+                None if !details.is_free => return false,
+                _ => {}
+            }
+        }
+        true
+    }
+}
+
+/// 大文字と小文字を区別せずに `text` が `pattern` を含むか
+fn contains(text: &str, pattern: &str) -> bool {
+    text.to_lowercase().contains(&pattern.to_lowercase())
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut conditions = Vec::new();
+        for query in &self.queries {
+            conditions.push(format!("名前「{query}」"));
+        }
+        for genre in &self.genres {
+            conditions.push(format!("ジャンル「{genre}」"));
+        }
+        if let Some(max_price) = self.max_price {
+            conditions.push(format!("{max_price}円以下"));
+        }
+        f.write_str(&conditions.join("・"))
+    }
+}
+
+/// 一覧を作るときにライブラリを読んだメンバー
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+pub struct Member {
+    pub user_id: UserId,
+    /// サーバーでの表示名
+    pub name: String,
+}
+
+/// ゲームを所有しているメンバーとそのプレイ時間
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+pub struct Owner {
+    pub user_id: UserId,
+    /// 合計のプレイ時間 (分)
+    pub playtime_forever: u64,
+}
+
+/// 一覧の種類
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ListKind {
+    /// 全員のライブラリに共通しているゲーム
+    #[default]
+    Library,
+    /// 全員がウィッシュリストに入れているか持っているゲーム
+    Wishlist,
+}
+
+impl ListKind {
+    fn title(&self) -> &'static str {
+        match self {
+            ListKind::Library => "Games",
+            ListKind::Wishlist => "Wishlist",
+        }
+    }
+
+    fn empty_text(&self) -> &'static str {
+        match self {
+            ListKind::Library => "共通のゲームはありませんでした。",
+            ListKind::Wishlist => "全員が欲しがっているゲームはありませんでした。",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
 pub struct CommonGamesStore {
+    #[serde(default)]
+    kind: ListKind,
     games: HashMap<AppId, Game>,
     game_ids: Vec<AppId>,
+    #[serde(default)]
+    members: Vec<Member>,
+    /// ゲームごとの所有しているメンバー
+    #[serde(default)]
+    owners: HashMap<AppId, Vec<Owner>>,
+    /// 全員は持っていないが、無料なので全員が持っているものとして加えたゲーム
+    #[serde(default)]
+    free_to_play: HashSet<AppId>,
+    /// 誰かが Steam ファミリーから借りて遊ぶゲーム
+    #[serde(default)]
+    family_shared: HashSet<AppId>,
+    layout: Layout,
+    /// 絞り込んだ一覧のときの条件
+    #[serde(default)]
+    filter: Option<Filter>,
+    /// 絞り込むときにストアの情報を調べきれず、一覧から除いたゲームの数
+    #[serde(default)]
+    unchecked: usize,
+    /// 一覧を作ったユーザー
+    /// このユーザーだけがページを送ることができる
+    owner: UserId,
+    /// 一覧を作ったコマンドの名前
+    ///
+    /// 操作できないときの案内に使う
+    command: String,
+    /// 一覧を作った時刻
+    created_at: Timestamp,
 }
 
 impl CommonGamesStore {
-    pub fn new(games: Vec<HashSet<Game>>) -> CommonGamesStore {
-        let games = games
+    /// 保存するときのキーの接頭辞
+    pub const PERSIST_KEY_PREFIX: &'static str = "common-games-";
+
+    /// メンバー全員のライブラリに共通しているゲームの一覧を作る
+    ///
+    /// `hidden` に含まれるゲームと、ツールやサウンドトラックなどゲームでないものは除く。
+    /// ゲームかどうかはストアの情報で判断し、一度に問い合わせきれないものは名前から推測する。
+    /// `free_games` は全員が持っているものとして加える。
+    /// Steam ファミリーから借りられるゲームも持っているものとして扱い、印をつける
+    pub async fn new(
+        owner: UserId,
+        command: &str,
+        layout: Layout,
+        libraries: Vec<(Member, Library)>,
+        hidden: &HashSet<AppId>,
+        free_games: &[Game],
+        client: &StoreApiClient,
+    ) -> CommonGamesStore {
+        let games: Vec<Game> = libraries
+            .iter()
+            .map(|(_, library)| library.keys().copied().collect::<HashSet<_>>())
+            .reduce(|acc, x| acc.intersection(&x).copied().collect())
+            .unwrap_or_default()
             .into_iter()
-            .reduce(|acc, x| {
-                acc.intersection(&x)
-                    .map(ToOwned::to_owned)
-                    .collect::<HashSet<_>>()
+            .filter(|id| !hidden.contains(id))
+            .map(|id| libraries[0].1[&id].game.clone())
+            .collect();
+        let mut games = client.filter_games(games, usize::MAX).await;
+        let free_to_play = free_games
+            .iter()
+            .filter(|game| !hidden.contains(&game.appid))
+            .filter(|game| games.iter().all(|g| g.appid != game.appid))
+            .cloned()
+            .collect::<Vec<_>>();
+        let free_to_play_ids = free_to_play.iter().map(|game| game.appid).collect();
+        let family_shared = games
+            .iter()
+            .map(|game| game.appid)
+            .filter(|id| {
+                libraries
+                    .iter()
+                    .any(|(_, library)| library.get(id).is_some_and(|game| game.family_shared))
             })
-            .unwrap_or_default();
+            .collect();
+        games.extend(free_to_play);
+        let mut store = CommonGamesStore::with_games(
+            owner,
+            command,
+            layout,
+            ListKind::Library,
+            libraries,
+            games,
+        );
+        store.free_to_play = free_to_play_ids;
+        store.family_shared = family_shared;
+        store
+    }
+
+    /// 全員がウィッシュリストに入れているか持っていて、誰かが欲しがっているゲームの一覧を作る
+    ///
+    /// 名前は呼び出す側で引いておく
+    pub fn wishlist(
+        owner: UserId,
+        command: &str,
+        layout: Layout,
+        libraries: Vec<(Member, Library)>,
+        games: Vec<Game>,
+    ) -> CommonGamesStore {
+        CommonGamesStore::with_games(owner, command, layout, ListKind::Wishlist, libraries, games)
+    }
+
+    /// ライブラリから `games` のそれぞれを持っているメンバーを記録して一覧を作る
+    fn with_games(
+        owner: UserId,
+        command: &str,
+        layout: Layout,
+        kind: ListKind,
+        libraries: Vec<(Member, Library)>,
+        games: Vec<Game>,
+    ) -> CommonGamesStore {
         let mut game_ids = games.iter().map(|game| game.appid).collect::<Vec<_>>();
         game_ids.sort();
         let games = games.into_iter().map(|game| (game.appid, game)).collect();
-        CommonGamesStore { games, game_ids }
+        let owners = game_ids
+            .iter()
+            .map(|id| {
+                let owners = libraries
+                    .iter()
+                    // ファミリーから借りているだけのメンバーは持っている人に数えない
+                    .filter_map(|(member, library)| {
+                        let game = library.get(id).filter(|game| !game.family_shared)?;
+                        Some(Owner {
+                            user_id: member.user_id,
+                            playtime_forever: game.playtime_forever,
+                        })
+                    })
+                    .collect();
+                (*id, owners)
+            })
+            .collect();
+        let members = libraries.into_iter().map(|(member, _)| member).collect();
+        CommonGamesStore {
+            kind,
+            games,
+            game_ids,
+            members,
+            owners,
+            free_to_play: HashSet::new(),
+            family_shared: HashSet::new(),
+            layout,
+            filter: None,
+            unchecked: 0,
+            owner,
+            command: command.to_string(),
+            created_at: Timestamp::now(),
+        }
+    }
+
+    /// 条件に合うゲームだけに絞り込んだ一覧を作る
+    ///
+    /// 絞り込んだ一覧をさらに絞り込むときは、前の条件と合わせた条件で絞り込む。
+    /// ジャンルや価格で絞り込むときはストアの情報を取得するが、キャッシュしていないものは
+    /// [`MAX_FILTER_LOOKUPS`] 件までしか問い合わせず、調べられなかったゲームは一覧に含めない
+    pub async fn filter(&self, filter: Filter, client: &StoreApiClient) -> CommonGamesStore {
+        let filter = match &self.filter {
+            Some(previous) => previous.clone().and(filter),
+            None => filter,
+        };
+        let candidates = self
+            .game_ids
+            .iter()
+            .copied()
+            .filter(|id| filter.matches_name(&self.games[id]))
+            .collect::<Vec<_>>();
+        let (details, unchecked) = if filter.needs_details() {
+            let (details, unchecked) = client
+                .get_app_details_within(candidates.iter().copied(), MAX_FILTER_LOOKUPS)
+                .await;
+            (details, unchecked.len())
+        } else {
+            (HashMap::new(), 0)
+        };
+        let game_ids = candidates
+            .into_iter()
+            .filter(|id| filter.matches(&self.games[id], details.get(id)))
+            .collect::<Vec<_>>();
+        let games = game_ids
+            .iter()
+            .map(|id| (*id, self.games[id].clone()))
+            .collect();
+        let owners = game_ids
+            .iter()
+            .filter_map(|id| Some((*id, self.owners.get(id)?.clone())))
+            .collect();
+        CommonGamesStore {
+            kind: self.kind,
+            games,
+            game_ids,
+            members: self.members.clone(),
+            owners,
+            free_to_play: self.free_to_play.clone(),
+            family_shared: self.family_shared.clone(),
+            layout: self.layout,
+            filter: Some(filter),
+            unchecked,
+            owner: self.owner,
+            command: self.command.clone(),
+            created_at: Timestamp::now(),
+        }
+    }
+
+    /// 共通のゲームの数
+    pub fn len(&self) -> usize {
+        self.game_ids.len()
+    }
+
+    /// ページの数
+    /// ゲームがないときも空のページを 1 つ表示するので 1 になる
+    pub fn page_count(&self) -> usize {
+        self.game_ids.len().div_ceil(self.layout.page_size).max(1)
     }
 
     pub fn get(&self, page_idx: usize) -> Vec<&Game> {
         let ids = self
             .game_ids
-            .chunks(PAGE_SIZE)
+            .chunks(self.layout.page_size)
             .nth(page_idx)
             .unwrap_or_default();
         let mut games = self
@@ -48,48 +470,284 @@ impl CommonGamesStore {
             .filter(|(id, _)| ids.contains(id))
             .map(|(_, game)| game)
             .collect::<Vec<_>>();
-        games.sort_by(|a, b| a.appid.cmp(&b.appid));
+        games.sort_by_key(|game| game.appid);
         games
     }
 
-    pub fn load(key: &str, persist: &PersistInstance) -> Result<CommonGamesStore> {
-        let self_ = persist.load(key)?;
-        Ok(self_)
+    /// 詳しく表示するときは、ページに表示するゲームのストアの情報を取得する
+    pub async fn get_details(
+        &self,
+        page_idx: usize,
+        client: &StoreApiClient,
+    ) -> HashMap<AppId, AppDetails> {
+        if !self.layout.detailed {
+            return HashMap::new();
+        }
+        let ids = self
+            .get(page_idx)
+            .iter()
+            .map(|game| game.appid)
+            .collect::<Vec<_>>();
+        client.get_app_details_with_reviews(ids).await
     }
 
-    pub fn save(&self, key: &str, persist: &PersistInstance) -> Result<()> {
-        persist.save(key, self)?;
+    /// ゲームを持っているメンバーの名前
+    fn owner_names(&self, appid: AppId) -> String {
+        let names = self
+            .owners
+            .get(&appid)
+            .into_iter()
+            .flatten()
+            .filter_map(|owner| {
+                self.members
+                    .iter()
+                    .find(|member| member.user_id == owner.user_id)
+            })
+            .map(|member| member.name.as_str())
+            .collect::<Vec<_>>();
+        if names.is_empty() {
+            "なし".to_string()
+        } else {
+            names.join("、")
+        }
+    }
+
+    /// すべてのゲームを CSV にする
+    ///
+    /// 所有者とプレイ時間 (分) は `;` 区切りで同じ順に並べる
+    pub fn to_csv(&self) -> String {
+        fn escape(field: &str) -> String {
+            if field.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.to_string()
+            }
+        }
+
+        let mut csv =
+            String::from("appid,name,owners,playtimes,free_to_play,family_shared,store_url\r\n");
+        for entry in self.export_entries() {
+            let owners = entry
+                .owners
+                .iter()
+                .map(|owner| owner.name.as_str())
+                .collect::<Vec<_>>()
+                .join(";");
+            let playtimes = entry
+                .owners
+                .iter()
+                .map(|owner| owner.playtime_forever.to_string())
+                .collect::<Vec<_>>()
+                .join(";");
+            csv.push_str(&format!(
+                "{},{},{},{},{},{},{}\r\n",
+                entry.appid,
+                escape(&entry.name),
+                escape(&owners),
+                playtimes,
+                entry.free_to_play,
+                entry.family_shared,
+                entry.store_url
+            ));
+        }
+        csv
+    }
+
+    /// すべてのゲームを JSON にする
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(&self.export_entries())?)
+    }
+
+    fn export_entries(&self) -> Vec<ExportEntry> {
+        self.game_ids
+            .iter()
+            .map(|id| {
+                let owners = self
+                    .owners
+                    .get(id)
+                    .into_iter()
+                    .flatten()
+                    .map(|owner| ExportOwner {
+                        name: self
+                            .members
+                            .iter()
+                            .find(|member| member.user_id == owner.user_id)
+                            .map(|member| member.name.clone())
+                            .unwrap_or_else(|| owner.user_id.to_string()),
+                        user_id: owner.user_id,
+                        playtime_forever: owner.playtime_forever,
+                    })
+                    .collect();
+                ExportEntry {
+                    appid: *id,
+                    name: self.games[id].name.clone(),
+                    owners,
+                    free_to_play: self.free_to_play.contains(id),
+                    family_shared: self.family_shared.contains(id),
+                    store_url: store_url(*id),
+                }
+            })
+            .collect()
+    }
+
+    pub fn load(key: &str, storage: &Storage) -> Result<CommonGamesStore, LoadError> {
+        storage.load(key)
+    }
+
+    pub fn save(&self, key: &str, storage: &Storage) -> Result<()> {
+        storage.save(key, self)?;
         Ok(())
     }
+
+    /// 一覧を作ったコマンドのインタラクションの ID から保存するキーを作る
+    ///
+    /// 同じユーザーが複数のサーバーで呼び出しても別々に保存される
+    pub fn generate_persist_key(interaction_id: InteractionId) -> String {
+        format!("{}{interaction_id}", Self::PERSIST_KEY_PREFIX)
+    }
+
+    /// 作られてから `ttl` 以上経っているか
+    pub fn is_expired(&self, ttl: Duration) -> bool {
+        let elapsed = Timestamp::now().unix_timestamp() - self.created_at.unix_timestamp();
+        elapsed >= ttl.as_secs() as i64
+    }
+
+    /// 期限切れと読めなくなった一覧を削除して、削除した数を返す
+    pub fn collect_garbage(storage: &Storage, ttl: Duration) -> Result<usize> {
+        let mut removed = 0;
+        for key in storage.keys(Self::PERSIST_KEY_PREFIX)? {
+            let expired = match Self::load(&key, storage) {
+                Ok(store) => store.is_expired(ttl),
+                Err(LoadError::Missing) => false,
+                Err(LoadError::Corrupt(_)) => true,
+            };
+            if expired {
+                storage.remove(&key)?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
 }
 
-/// ボタンに設定するカスタムID
+/// 出力するときの 1 件
+#[derive(Serialize, Debug)]
+struct ExportEntry {
+    appid: AppId,
+    name: String,
+    owners: Vec<ExportOwner>,
+    /// 無料なので全員が持っているものとして扱った
+    free_to_play: bool,
+    /// 誰かが Steam ファミリーから借りて遊ぶ
+    family_shared: bool,
+    store_url: String,
+}
+
+#[derive(Serialize, Debug)]
+struct ExportOwner {
+    user_id: UserId,
+    name: String,
+    /// 合計のプレイ時間 (分)
+    playtime_forever: u64,
+}
+
+/// 古いバージョンは使い捨てのデータなので変換はせずに読めないものとして扱う
+///
+/// - バージョン 1 は呼び出したユーザーの ID をキーにして期限なしで保存していた
+/// - バージョン 2 は一覧を作ったユーザーを持っていなかった
+/// - バージョン 3 は表示のしかたを持っていなかった
+/// - バージョン 4 は一覧を作ったコマンドを持っていなかった
+/// - バージョン 5 は絞り込みの条件を 1 つずつしか持てなかった
+impl Record for CommonGamesStore {
+    const VERSION: u32 = 6;
+}
+
+/// ボタンとセレクトメニューに設定するカスタムID
 #[derive(Serialize, Deserialize, Debug)]
-pub struct CommonGamesButtonCustomId {
-    /// 遷移先のページの ID
-    pub page: usize,
+pub struct CommonGamesCustomId {
     /// ストアのキー
-    /// 呼び出したユーザーの ID に紐づけて保存する
+    /// 呼び出したコマンドのインタラクションの ID に紐づけて保存する
     pub key: String,
+    pub action: CommonGamesAction,
+}
+
+/// コンポーネントが操作されたときの動作
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CommonGamesAction {
+    /// 指定したページに移動する
+    /// 同じメッセージ内でカスタムIDが重複しないように、どのボタンかも持たせる
+    Page(usize, PageButton),
+    /// セレクトメニューで選んだページに移動する
+    Jump,
+    /// 絞り込みのモーダルを開く
+    /// モーダルを送信したときにも同じカスタムIDを使う
+    Filter,
+    /// 一覧をファイルにして添付する
+    Export(ExportFormat),
+    /// セレクトメニューで選んだゲームを遊ぶことにして、起動するリンクをチャンネルに投稿する
+    Pick,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+impl ExportFormat {
+    fn label(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "CSVで出力",
+            ExportFormat::Json => "JSONで出力",
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Json => "json",
+        }
+    }
 }
 
-impl CommonGamesButtonCustomId {
-    pub fn new(page: usize, key: String) -> CommonGamesButtonCustomId {
-        CommonGamesButtonCustomId { page, key }
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PageButton {
+    First,
+    Prev,
+    Next,
+    Last,
+}
+
+impl PageButton {
+    fn label(&self) -> &'static str {
+        match self {
+            PageButton::First => "FIRST",
+            PageButton::Prev => "PREV",
+            PageButton::Next => "NEXT",
+            PageButton::Last => "LAST",
+        }
     }
 
-    pub fn prev(&self) -> Option<CommonGamesButtonCustomId> {
-        self.page
-            .checked_sub(1)
-            .map(|page| CommonGamesButtonCustomId::new(page, self.key.clone()))
+    /// `page` を表示しているときに、このボタンで移動する先のページ
+    fn target(&self, page: usize, page_count: usize) -> Option<usize> {
+        let last = page_count.saturating_sub(1);
+        let target = match self {
+            PageButton::First => 0,
+            PageButton::Prev => page.checked_sub(1)?,
+            PageButton::Next => page + 1,
+            PageButton::Last => last,
+        };
+        (target != page && target <= last).then_some(target)
     }
+}
 
-    pub fn next(&self) -> CommonGamesButtonCustomId {
-        CommonGamesButtonCustomId::new(self.page + 1, self.key.clone())
+impl CommonGamesCustomId {
+    pub fn new(key: String, action: CommonGamesAction) -> CommonGamesCustomId {
+        CommonGamesCustomId { key, action }
     }
 }
 
-impl FromStr for CommonGamesButtonCustomId {
+impl FromStr for CommonGamesCustomId {
     type Err = anyhow::Error;
     fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
         let id = serde_json::from_str(s)?;
@@ -97,44 +755,652 @@ impl FromStr for CommonGamesButtonCustomId {
     }
 }
 
-impl ToString for CommonGamesButtonCustomId {
-    fn to_string(&self) -> String {
-        serde_json::to_string(&self).expect("convert id to string error")
+impl fmt::Display for CommonGamesCustomId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&serde_json::to_string(&self).expect("convert id to string error"))
     }
 }
 
-pub fn create_interaction_response(
-    custom_id: CommonGamesButtonCustomId,
-    games: Vec<&Game>,
-    ephemeral: bool,
-    msg: &mut CreateInteractionResponseData,
-) {
-    let text = games
-        .iter()
-        .map(|game| {
-            format!(
-                "- [{}](https://store.steampowered.com/app/{})\n",
-                game.name, game.appid
-            )
+/// どのコマンドで作ったかわからない一覧が操作されたときの案内
+pub const EXPIRED_NOTICE: &str =
+    "この一覧は有効期限が切れました。もう一度コマンドを実行してください。";
+
+/// 操作したユーザーが使うことのできる一覧を読む
+///
+/// 使うことができないときは、代わりに表示するメッセージを返す
+fn load_for_user(
+    key: &str,
+    user_id: UserId,
+    storage: &Storage,
+    ttl: Duration,
+) -> std::result::Result<CommonGamesStore, String> {
+    match CommonGamesStore::load(key, storage) {
+        Ok(store) if store.is_expired(ttl) => Err(format!(
+            "この一覧は有効期限が切れました。もう一度 `/{}` を実行してください。",
+            store.command
+        )),
+        Ok(store) if store.owner != user_id => Err(format!(
+            "この一覧を操作できるのは `/{}` を実行した人だけです。",
+            store.command
+        )),
+        Ok(store) => Ok(store),
+        // 消されたあとや古いバージョンの一覧は、どのコマンドで作ったかわからない
+        Err(_) => Err(EXPIRED_NOTICE.to_string()),
+    }
+}
+
+/// ボタンやセレクトメニューが操作されたときの処理
+///
+/// ページを移動するときは元のメッセージを指定されたページに書き換える
+pub async fn handle_component(
+    ctx: impl AsRef<Http>,
+    component: &MessageComponentInteraction,
+    custom_id: CommonGamesCustomId,
+    storage: &Storage,
+    steam: &SteamApiClient,
+    store_client: &StoreApiClient,
+    ttl: Duration,
+) -> Result<()> {
+    let store = match load_for_user(&custom_id.key, component.user.id, storage, ttl) {
+        Ok(store) => store,
+        Err(notice) => {
+            component
+                .create_interaction_response(ctx, |response| {
+                    response
+                        .kind(InteractionResponseType::ChannelMessageWithSource)
+                        .interaction_response_data(|msg| msg.ephemeral(true).content(notice))
+                })
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let page = match custom_id.action {
+        CommonGamesAction::Page(page, _) => page,
+        CommonGamesAction::Jump => component
+            .data
+            .values
+            .first()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or_default(),
+        CommonGamesAction::Export(format) => {
+            let data = match format {
+                ExportFormat::Csv => store.to_csv(),
+                ExportFormat::Json => store.to_json()?,
+            };
+            component
+                .create_interaction_response(ctx, |response| {
+                    response
+                        .kind(InteractionResponseType::ChannelMessageWithSource)
+                        .interaction_response_data(|msg| {
+                            msg.ephemeral(true);
+                            if data.len() > MAX_ATTACHMENT_SIZE {
+                                msg.content("一覧が大きすぎて添付できませんでした。絞り込んでからもう一度試してください。")
+                            } else {
+                                msg.content(format!("{}件のゲームを出力しました。", store.len()))
+                                    .add_file(AttachmentType::Bytes {
+                                        data: data.into_bytes().into(),
+                                        filename: format!("common-games.{}", format.extension()),
+                                    })
+                            }
+                        })
+                })
+                .await?;
+            return Ok(());
+        }
+        CommonGamesAction::Pick => {
+            let Some(game) = component
+                .data
+                .values
+                .first()
+                .and_then(|value| value.parse().ok())
+                .and_then(|appid: AppId| store.games.get(&appid))
+            else {
+                component
+                    .create_interaction_response(ctx, |response| {
+                        response
+                            .kind(InteractionResponseType::ChannelMessageWithSource)
+                            .interaction_response_data(|msg| {
+                                msg.ephemeral(true)
+                                    .content("選んだゲームが一覧に見つかりませんでした。")
+                            })
+                    })
+                    .await?;
+                return Ok(());
+            };
+            // ロビーを調べるのに時間がかかるので、先に応答しておく
+            // 全員が見られるように一覧とは別にチャンネルに投稿する
+            component
+                .create_interaction_response(&ctx, |response| {
+                    response.kind(InteractionResponseType::DeferredChannelMessageWithSource)
+                })
+                .await?;
+            let content = launch_text(game, &store.members, steam, storage).await;
+            component
+                .edit_original_interaction_response(&ctx, |msg| {
+                    msg.content(content).components(|c| {
+                        c.create_action_row(|r| {
+                            r.create_button(|b| {
+                                b.style(ButtonStyle::Link)
+                                    .url(store_url(game.appid))
+                                    .label("ストアで見る")
+                            })
+                        })
+                    })
+                })
+                .await?;
+            return Ok(());
+        }
+        CommonGamesAction::Filter => {
+            component
+                .create_interaction_response(ctx, |response| {
+                    response
+                        .kind(InteractionResponseType::Modal)
+                        .interaction_response_data(|modal| {
+                            modal
+                                .custom_id(custom_id)
+                                .title("一覧を絞り込む")
+                                .components(|c| {
+                                    for (id, label, placeholder) in [
+                                        ("query", "名前", "名前に含まれる文字"),
+                                        (
+                                            "genre",
+                                            "ジャンル・カテゴリ",
+                                            "例: アクション, 協力プレイ",
+                                        ),
+                                        ("max-price", "価格の上限 (円)", "例: 2000"),
+                                    ] {
+                                        c.create_action_row(|r| {
+                                            r.create_input_text(|t| {
+                                                t.custom_id(id)
+                                                    .label(label)
+                                                    .placeholder(placeholder)
+                                                    .style(InputTextStyle::Short)
+                                                    .required(false)
+                                            })
+                                        });
+                                    }
+                                    c
+                                })
+                        })
+                })
+                .await?;
+            return Ok(());
+        }
+    };
+
+    // ストアの情報の取得に時間がかかることがあるので、先に応答しておく
+    component
+        .create_interaction_response(&ctx, |response| {
+            response.kind(InteractionResponseType::DeferredUpdateMessage)
+        })
+        .await?;
+    let details = store.get_details(page, store_client).await;
+    let (embeds, components) = create_page(&custom_id.key, &store, page, &details);
+    component
+        .edit_original_interaction_response(&ctx, |msg| {
+            msg.set_embeds(embeds).components(|c| {
+                *c = components;
+                c
+            })
+        })
+        .await?;
+    Ok(())
+}
+
+/// 絞り込みのモーダルが送信されたときに、絞り込んだ一覧を新しいメッセージで表示する
+///
+/// 絞り込んだ一覧は別のキーで保存するので、元の一覧とは別にページを送ることができる
+pub async fn handle_modal_submit(
+    ctx: impl AsRef<Http>,
+    modal: &ModalSubmitInteraction,
+    custom_id: CommonGamesCustomId,
+    storage: &Storage,
+    store_client: &StoreApiClient,
+    ttl: Duration,
+) -> Result<()> {
+    let store = match load_for_user(&custom_id.key, modal.user.id, storage, ttl) {
+        Ok(store) => store,
+        Err(notice) => {
+            modal
+                .create_interaction_response(ctx, |response| {
+                    response
+                        .kind(InteractionResponseType::ChannelMessageWithSource)
+                        .interaction_response_data(|msg| msg.ephemeral(true).content(notice))
+                })
+                .await?;
+            return Ok(());
+        }
+    };
+
+    modal
+        .create_interaction_response(&ctx, |response| {
+            response
+                .kind(InteractionResponseType::DeferredChannelMessageWithSource)
+                .interaction_response_data(|msg| msg.ephemeral(true))
         })
-        .collect::<String>();
-    msg.ephemeral(ephemeral)
-        .embed(|embed| embed.field(format!("Games: p{}", custom_id.page), text, false))
-        .components(|c| {
-            c.create_action_row(|r| {
+        .await?;
+
+    let filtered = store
+        .filter(Filter::from_modal(&modal.data.components), store_client)
+        .await;
+    let key = CommonGamesStore::generate_persist_key(modal.id);
+    filtered.save(&key, storage)?;
+    let details = filtered.get_details(0, store_client).await;
+    let (embeds, components) = create_page(&key, &filtered, 0, &details);
+    modal
+        .edit_original_interaction_response(&ctx, |msg| {
+            msg.set_embeds(embeds).components(|c| {
+                *c = components;
+                c
+            })
+        })
+        .await?;
+    Ok(())
+}
+
+/// 添付できるファイルの大きさの上限
+/// ブーストされていないサーバーでも添付できる大きさにしておく
+const MAX_ATTACHMENT_SIZE: usize = 8 * 1024 * 1024;
+
+/// セレクトメニューに並べられるページの数
+const MAX_SELECT_OPTIONS: usize = 25;
+
+/// `page` のページを表示する埋め込みとコンポーネントを作る
+///
+/// 詳しく表示するときは `details` に [`CommonGamesStore::get_details`] で取得した情報を渡す
+pub fn create_page(
+    key: &str,
+    store: &CommonGamesStore,
+    page: usize,
+    details: &HashMap<AppId, AppDetails>,
+) -> (Vec<CreateEmbed>, CreateComponents) {
+    let mut embeds = Vec::new();
+    let page_count = store.page_count();
+    let page = page.min(page_count - 1);
+    let games = store.get(page);
+    let mut summary = format!("{}/{}ページ・全{}件", page + 1, page_count, store.len());
+    if let Some(filter) = &store.filter {
+        summary.push_str(&format!("・絞り込み: {filter}"));
+    }
+    if store.unchecked > 0 {
+        summary.push_str(&format!("・{}件は調べきれずに除きました", store.unchecked));
+    }
+
+    if games.is_empty() {
+        let mut embed = CreateEmbed::default();
+        embed
+            .description(if store.filter.is_some() {
+                "条件に合うゲームはありませんでした。"
+            } else {
+                store.kind.empty_text()
+            })
+            .footer(|f| f.text(&summary));
+        embeds.push(embed);
+    } else if store.layout.detailed {
+        // ゲームごとに埋め込みを分けて、最後の埋め込みにページの情報を載せる
+        let last = games.len() - 1;
+        for (idx, game) in games.iter().enumerate() {
+            let mut embed = CreateEmbed::default();
+            embed.title(&game.name).url(store_url(game.appid));
+            match details.get(&game.appid) {
+                Some(details) => {
+                    if let Some(image) = details
+                        .capsule_image
+                        .as_ref()
+                        .or(details.header_image.as_ref())
+                    {
+                        embed.thumbnail(image);
+                    }
+                    embed.field("価格", details.price_text(), true);
+                    if let Some(review) = details.review_text() {
+                        embed.field("レビュー", review, true);
+                    }
+                    let badges = details.multiplayer_badges();
+                    if !badges.is_empty() {
+                        embed.field("プレイ人数", badges.join(" / "), false);
+                    }
+                }
+                None => {
+                    embed.description("ストアの情報を取得できませんでした。");
+                }
+            }
+            if store.free_to_play.contains(&game.appid) {
+                embed.field("無料", "持っていなくても全員が遊べます", true);
+            }
+            if store.family_shared.contains(&game.appid) {
+                embed.field(
+                    "ファミリー",
+                    "Steam ファミリーから借りて遊ぶ人がいます",
+                    true,
+                );
+            }
+            if store.kind == ListKind::Wishlist {
+                embed.field("持っている人", store.owner_names(game.appid), false);
+            }
+            if idx == last {
+                embed.footer(|f| f.text(&summary));
+            }
+            embeds.push(embed);
+        }
+    } else {
+        let text = games
+            .iter()
+            .map(|game| {
+                let label = if store.free_to_play.contains(&game.appid) {
+                    " (無料)"
+                } else if store.family_shared.contains(&game.appid) {
+                    " (ファミリー)"
+                } else {
+                    ""
+                };
+                format!("- [{}]({}){label}\n", game.name, store_url(game.appid))
+            })
+            .collect::<String>();
+        let mut embed = CreateEmbed::default();
+        embed
+            .title(store.kind.title())
+            .description(text)
+            .footer(|f| f.text(&summary));
+        embeds.push(embed);
+    }
+
+    // セレクトメニューには今のページを中心に並べられるだけ並べる
+    let first_option = page
+        .saturating_sub(MAX_SELECT_OPTIONS / 2)
+        .min(page_count.saturating_sub(MAX_SELECT_OPTIONS));
+    let options = first_option..page_count.min(first_option + MAX_SELECT_OPTIONS);
+
+    let mut components = CreateComponents::default();
+    components
+        .create_action_row(|r| {
+            for button in [
+                PageButton::First,
+                PageButton::Prev,
+                PageButton::Next,
+                PageButton::Last,
+            ] {
+                // 移動先がないボタンも、カスタムIDが重複しないように今のページで作って無効にしておく
+                let target = button.target(page, page_count);
+                let custom_id = CommonGamesCustomId::new(
+                    key.to_string(),
+                    CommonGamesAction::Page(target.unwrap_or(page), button),
+                );
                 r.create_button(|b| {
-                    b.label("PREV");
-                    if let Some(prev) = custom_id.prev() {
-                        b.custom_id(prev.to_string())
-                    } else {
-                        b.disabled(true).custom_id("Invalid")
+                    b.custom_id(custom_id)
+                        .label(button.label())
+                        .disabled(target.is_none())
+                });
+            }
+            r.create_button(|b| {
+                b.custom_id(CommonGamesCustomId::new(
+                    key.to_string(),
+                    CommonGamesAction::Filter,
+                ))
+                .label("絞り込み")
+                .style(ButtonStyle::Secondary)
+            })
+        })
+        .create_action_row(|r| {
+            r.create_select_menu(|m| {
+                m.custom_id(CommonGamesCustomId::new(
+                    key.to_string(),
+                    CommonGamesAction::Jump,
+                ))
+                .placeholder("ページを選んで移動")
+                .disabled(page_count <= 1)
+                .options(|o| {
+                    for idx in options {
+                        o.create_option(|opt| {
+                            opt.label(format!("{}ページ", idx + 1))
+                                .value(idx)
+                                .default_selection(idx == page)
+                        });
                     }
+                    o
                 })
-                .create_button(|b| {
-                    b.custom_id(custom_id.next().to_string())
-                        .label("NEXT")
-                        .disabled(games.len() != PAGE_SIZE)
+            })
+        })
+        .create_action_row(|r| {
+            for format in [ExportFormat::Csv, ExportFormat::Json] {
+                r.create_button(|b| {
+                    b.custom_id(CommonGamesCustomId::new(
+                        key.to_string(),
+                        CommonGamesAction::Export(format),
+                    ))
+                    .label(format.label())
+                    .style(ButtonStyle::Secondary)
+                    .disabled(store.len() == 0)
+                });
+            }
+            r
+        });
+    if !games.is_empty() {
+        components.create_action_row(|r| {
+            r.create_select_menu(|m| {
+                m.custom_id(CommonGamesCustomId::new(
+                    key.to_string(),
+                    CommonGamesAction::Pick,
+                ))
+                .placeholder("遊ぶゲームを選んで起動のリンクを投稿")
+                .options(|o| {
+                    for game in &games {
+                        o.create_option(|opt| {
+                            // ラベルは 100 文字まで
+                            opt.label(game.name.chars().take(100).collect::<String>())
+                                .value(game.appid)
+                        });
+                    }
+                    o
                 })
             })
         });
+    }
+
+    (embeds, components)
+}
+
+/// 選んだゲームを起動するリンクと、メンバーが入っているロビーに参加するリンク
+///
+/// Discord のリンクボタンは `steam://` を使えないので本文に書く
+async fn launch_text(
+    game: &Game,
+    members: &[Member],
+    steam: &SteamApiClient,
+    storage: &Storage,
+) -> String {
+    let mut text = format!(
+        "**{}** を遊びましょう！\n起動: steam://run/{}",
+        game.name, game.appid
+    );
+
+    let users = members
+        .iter()
+        .filter_map(|member| {
+            let user = User::load(&member.user_id.to_string(), storage).ok()?;
+            Some((member, user))
+        })
+        .collect::<Vec<_>>();
+    let summaries = steam
+        .get_player_summaries(
+            &users
+                .iter()
+                .map(|(_, user)| user.steam_id())
+                .collect::<Vec<_>>(),
+        )
+        .await
+        .unwrap_or_else(|e| {
+            tracing::warn!("{e:?}");
+            Vec::new()
+        });
+    for (member, user) in &users {
+        let Some(summary) = summaries.iter().find(|s| s.steamid == user.steam_id()) else {
+            continue;
+        };
+        // 選んだゲームのロビーにいるときだけ
+        if summary.gameid.as_deref() != Some(game.appid.to_string().as_str()) {
+            continue;
+        }
+        if let Some(lobby) = &summary.lobbysteamid {
+            text.push_str(&format!(
+                "\n{}のロビーに参加: steam://joinlobby/{}/{lobby}/{}",
+                member.name, game.appid, summary.steamid
+            ));
+        }
+    }
+    text
+}
+
+pub fn store_url(appid: AppId) -> String {
+    format!("https://store.steampowered.com/app/{appid}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::{Category, Price};
+
+    fn game(appid: AppId, name: &str) -> Game {
+        Game {
+            appid,
+            name: name.to_string(),
+        }
+    }
+
+    fn details(genres: &[&str], is_free: bool, final_price: Option<u64>) -> AppDetails {
+        AppDetails {
+            appid: 1,
+            name: String::new(),
+            kind: "game".to_string(),
+            header_image: None,
+            capsule_image: None,
+            is_free,
+            price: final_price.map(|final_| Price {
+                currency: "JPY".to_string(),
+                initial: final_,
+                final_,
+                discount_percent: 0,
+                initial_formatted: String::new(),
+                final_formatted: String::new(),
+            }),
+            categories: vec![Category {
+                id: 1,
+                description: "マルチプレイヤー".to_string(),
+            }],
+            genres: genres.iter().map(|genre| genre.to_string()).collect(),
+            review: None,
+        }
+    }
+
+    fn store(games: &[Game], page_size: usize) -> CommonGamesStore {
+        let member = Member {
+            user_id: UserId(1),
+            name: "a, \"b\"".to_string(),
+        };
+        CommonGamesStore {
+            kind: ListKind::Library,
+            games: games
+                .iter()
+                .map(|game| (game.appid, game.clone()))
+                .collect(),
+            game_ids: games.iter().map(|game| game.appid).collect(),
+            members: vec![member.clone()],
+            owners: games
+                .iter()
+                .map(|game| {
+                    let owner = Owner {
+                        user_id: member.user_id,
+                        playtime_forever: 60,
+                    };
+                    (game.appid, vec![owner])
+                })
+                .collect(),
+            free_to_play: HashSet::new(),
+            family_shared: HashSet::new(),
+            layout: Layout::new(Some(page_size), false),
+            filter: None,
+            unchecked: 0,
+            owner: member.user_id,
+            command: "common-games".to_string(),
+            created_at: Timestamp::now(),
+        }
+    }
+
+    #[test]
+    fn filter_matches_all_conditions() {
+        let filter = Filter::by_query("portal".to_string()).and(Filter {
+            queries: vec!["2".to_string()],
+            genres: vec!["パズル".to_string()],
+            max_price: Some(1000),
+        });
+        let portal2 = game(620, "Portal 2");
+        assert!(filter.matches(&portal2, Some(&details(&["パズル"], false, Some(98000)))));
+        assert!(!filter.matches(
+            &game(400, "Portal"),
+            Some(&details(&["パズル"], false, Some(98000)))
+        ));
+        assert!(!filter.matches(
+            &portal2,
+            Some(&details(&["アクション"], false, Some(98000)))
+        ));
+        assert!(!filter.matches(&portal2, Some(&details(&["パズル"], false, Some(198000)))));
+        assert!(!filter.matches(&portal2, Some(&details(&["パズル"], false, None))));
+        assert!(!filter.matches(&portal2, None));
+        // カテゴリでも、無料のゲームも条件に合う
+        let multiplayer = Filter {
+            genres: vec!["マルチ".to_string()],
+            max_price: Some(0),
+            ..Default::default()
+        };
+        assert!(multiplayer.matches(&portal2, Some(&details(&[], true, None))));
+    }
+
+    #[test]
+    fn combined_filter_keeps_the_lower_price() {
+        let filter = Filter {
+            max_price: Some(1000),
+            ..Default::default()
+        }
+        .and(Filter {
+            max_price: Some(2000),
+            ..Default::default()
+        });
+        assert_eq!(filter.max_price, Some(1000));
+        assert_eq!(filter.to_string(), "1000円以下");
+    }
+
+    #[test]
+    fn exports_escape_names() {
+        let store = store(&[game(1, "Hello, \"World\"\nAgain")], 10);
+        let csv = store.to_csv();
+        let row = csv.split("\r\n").nth(1).unwrap();
+        assert!(
+            row.starts_with("1,\"Hello, \"\"World\"\"\nAgain\",\"a, \"\"b\"\"\",60,"),
+            "{row}"
+        );
+
+        let json: serde_json::Value = serde_json::from_str(&store.to_json().unwrap()).unwrap();
+        assert_eq!(json[0]["name"], "Hello, \"World\"\nAgain");
+        assert_eq!(json[0]["owners"][0]["name"], "a, \"b\"");
+    }
+
+    #[test]
+    fn counts_pages() {
+        let games = (1..=21).map(|appid| game(appid, "g")).collect::<Vec<_>>();
+        assert_eq!(store(&games, 10).page_count(), 3);
+        assert_eq!(store(&games[..20], 10).page_count(), 2);
+        assert_eq!(store(&[], 10).page_count(), 1);
+    }
+
+    #[test]
+    fn page_buttons_skip_the_current_page() {
+        assert_eq!(PageButton::First.target(2, 3), Some(0));
+        assert_eq!(PageButton::First.target(0, 3), None);
+        assert_eq!(PageButton::Prev.target(0, 3), None);
+        assert_eq!(PageButton::Prev.target(2, 3), Some(1));
+        assert_eq!(PageButton::Next.target(1, 3), Some(2));
+        assert_eq!(PageButton::Next.target(2, 3), None);
+        assert_eq!(PageButton::Last.target(0, 3), Some(2));
+        assert_eq!(PageButton::Last.target(0, 1), None);
+    }
 }
@@ -0,0 +1,73 @@
+use std::{collections::HashMap, fs, path::Path, time::Duration};
+
+use anyhow::{anyhow, Context, Result};
+
+/// `STORAGE_DIR` が指定されなかったときの保存先
+const DEFAULT_STORAGE_DIR: &str = "data";
+
+/// `COMMON_GAMES_TTL_SECS` が指定されなかったときの共通ゲームの一覧の有効期限
+const DEFAULT_COMMON_GAMES_TTL_SECS: u64 = 24 * 60 * 60;
+
+/// bot の設定
+///
+/// キーの名前は `Secrets.toml` と揃えてある
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub discord_token: String,
+    pub steam_api_key: String,
+    /// 単体で動かすときの保存先のディレクトリ
+    pub storage_dir: String,
+    /// 共通ゲームの一覧のページ送りができる期間
+    pub common_games_ttl: Duration,
+    /// 特権のプレゼンスの intent を要求する
+    ///
+    /// Developer Portal で Presence Intent を有効にしたときだけ `true` にする
+    pub presence_intent: bool,
+}
+
+impl Config {
+    /// キーから値を引く関数をもとに設定を組み立てる
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Result<Config> {
+        let require = |key: &str| lookup(key).ok_or_else(|| anyhow!("'{key}' was not found"));
+        let secs = |key: &str, default: u64| -> Result<Duration> {
+            let secs = match lookup(key) {
+                Some(value) => value
+                    .parse()
+                    .with_context(|| format!("'{key}' must be a number of seconds"))?,
+                None => default,
+            };
+            Ok(Duration::from_secs(secs))
+        };
+        let flag = |key: &str| -> Result<bool> {
+            match lookup(key) {
+                Some(value) => value
+                    .parse()
+                    .with_context(|| format!("'{key}' must be true or false")),
+                None => Ok(false),
+            }
+        };
+        Ok(Config {
+            discord_token: require("DISCORD_TOKEN")?,
+            steam_api_key: require("STEAM_API_KEY")?,
+            storage_dir: lookup("STORAGE_DIR").unwrap_or_else(|| DEFAULT_STORAGE_DIR.to_string()),
+            common_games_ttl: secs("COMMON_GAMES_TTL_SECS", DEFAULT_COMMON_GAMES_TTL_SECS)?,
+            presence_intent: flag("PRESENCE_INTENT")?,
+        })
+    }
+
+    /// TOML の設定ファイルと環境変数から設定を読む
+    ///
+    /// 同じキーがあるときは環境変数を優先する
+    pub fn from_env_or_file(path: Option<&Path>) -> Result<Config> {
+        let file = match path {
+            Some(path) => {
+                let text = fs::read_to_string(path)
+                    .with_context(|| format!("failed to read {}", path.display()))?;
+                toml::from_str::<HashMap<String, String>>(&text)
+                    .with_context(|| format!("invalid config {}", path.display()))?
+            }
+            None => HashMap::new(),
+        };
+        Config::from_lookup(|key| std::env::var(key).ok().or_else(|| file.get(key).cloned()))
+    }
+}
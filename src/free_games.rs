@@ -0,0 +1,35 @@
+//! 持っていなくても全員が遊べる、無料のマルチプレイのゲーム
+//!
+//! GetOwnedGames は遊んだことのない無料のゲームを返さないので、よく遊ばれているものを手で並べておく
+
+use crate::steam::Game;
+
+const FREE_MULTIPLAYER_GAMES: &[(u64, &str)] = &[
+    (440, "Team Fortress 2"),
+    (570, "Dota 2"),
+    (730, "Counter-Strike 2"),
+    (230410, "Warframe"),
+    (236390, "War Thunder"),
+    (238960, "Path of Exile"),
+    (291550, "Brawlhalla"),
+    (386360, "SMITE"),
+    (444090, "Paladins"),
+    (578080, "PUBG: BATTLEGROUNDS"),
+    (1085660, "Destiny 2"),
+    (1172470, "Apex Legends"),
+    (1240440, "Halo Infinite"),
+    (1599340, "Lost Ark"),
+    (2073850, "THE FINALS"),
+    (2767030, "Marvel Rivals"),
+];
+
+/// 全員が持っているものとして扱う無料のゲーム
+pub fn free_multiplayer_games() -> Vec<Game> {
+    FREE_MULTIPLAYER_GAMES
+        .iter()
+        .map(|(appid, name)| Game {
+            appid: *appid,
+            name: name.to_string(),
+        })
+        .collect()
+}
@@ -0,0 +1,347 @@
+//! 参加を募ったゲーム会と、開始時刻に参加者の共通のゲームを投稿する処理
+
+use std::{collections::BTreeSet, fmt, str::FromStr, sync::Mutex, time::Duration};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serenity::{
+    builder::{CreateComponents, CreateEmbed},
+    client::Context,
+    http::Http,
+    model::{
+        application::{
+            component::ButtonStyle,
+            interaction::{
+                message_component::MessageComponentInteraction, InteractionResponseType,
+            },
+        },
+        id::{ChannelId, GuildId, InteractionId, MessageId, UserId},
+        Timestamp,
+    },
+};
+
+use crate::{
+    commands::gamenight,
+    common_games::{create_page, CommonGamesStore, Layout},
+    group::{fetch_members, load_libraries},
+    hidden::HiddenGames,
+    steam::SteamApiClient,
+    storage::{LoadError, Record, Storage},
+    store::StoreApiClient,
+};
+
+/// 開始時刻を過ぎても投稿できなかったゲーム会をあきらめるまでの時間
+///
+/// 止まっていた間に始まったゲーム会を、再起動したときに遅れて投稿しないようにする
+const GRACE_PERIOD: Duration = Duration::from_secs(60 * 60);
+
+/// ゲーム会を読んでから保存か削除をするまでの間に、ほかの処理が書き換えないようにする
+///
+/// 開始したのと同時に参加者を書き換えると、削除したゲーム会を保存しなおしてしまう
+static LOCK: Mutex<()> = Mutex::new(());
+
+/// 参加を募っているゲーム会
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GameNight {
+    pub guild_id: GuildId,
+    /// 募集を投稿して、開始時刻に共通のゲームを投稿するチャンネル
+    pub channel_id: ChannelId,
+    /// 募集のメッセージ
+    ///
+    /// 開始したらボタンを消す
+    pub message_id: Option<MessageId>,
+    pub title: String,
+    pub starts_at: Timestamp,
+    /// 募集したユーザー
+    /// 共通のゲームの一覧はこのユーザーが操作する
+    pub host: UserId,
+    pub attendees: BTreeSet<UserId>,
+}
+
+impl GameNight {
+    /// 保存するときのキーの接頭辞
+    pub const PERSIST_KEY_PREFIX: &'static str = "game-night-";
+
+    /// 募集したユーザーは最初から参加者に入れる
+    pub fn new(
+        guild_id: GuildId,
+        channel_id: ChannelId,
+        title: String,
+        starts_at: Timestamp,
+        host: UserId,
+    ) -> GameNight {
+        GameNight {
+            guild_id,
+            channel_id,
+            message_id: None,
+            title,
+            starts_at,
+            host,
+            attendees: BTreeSet::from([host]),
+        }
+    }
+
+    pub fn load(key: &str, storage: &Storage) -> Result<GameNight, LoadError> {
+        storage.load(key)
+    }
+
+    pub fn save(&self, key: &str, storage: &Storage) -> Result<()> {
+        storage.save(key, self)
+    }
+
+    /// まだ始まっていないゲーム会を読んで書き換え、保存する
+    ///
+    /// 開始の処理と同時に書き換えないように、読んでから保存するまでを順番に行う。
+    /// もう始まっているか読めなかったときは `None` を返す
+    pub fn update(
+        key: &str,
+        storage: &Storage,
+        f: impl FnOnce(&mut GameNight),
+    ) -> Result<Option<GameNight>> {
+        let _lock = LOCK.lock().unwrap();
+        let mut game_night = match Self::load(key, storage) {
+            Ok(game_night) if !game_night.is_started() => game_night,
+            _ => return Ok(None),
+        };
+        f(&mut game_night);
+        game_night.save(key, storage)?;
+        Ok(Some(game_night))
+    }
+
+    /// 募集したコマンドのインタラクションの ID から保存するキーを作る
+    pub fn generate_persist_key(interaction_id: InteractionId) -> String {
+        format!("{}{interaction_id}", Self::PERSIST_KEY_PREFIX)
+    }
+
+    fn is_started(&self) -> bool {
+        self.starts_at.unix_timestamp() <= Timestamp::now().unix_timestamp()
+    }
+
+    /// 募集のメッセージの埋め込みとボタン
+    pub fn create_message(&self, key: &str) -> (CreateEmbed, CreateComponents) {
+        let attendees = self
+            .attendees
+            .iter()
+            .map(|user_id| format!("<@{user_id}>"))
+            .collect::<Vec<_>>()
+            .join("、");
+        let mut embed = CreateEmbed::default();
+        embed
+            .title(&self.title)
+            .description(format!(
+                "<t:{0}:F> (<t:{0}:R>) に始めます。\n開始時刻に参加する人の共通のゲームを投稿します。",
+                self.starts_at.unix_timestamp()
+            ))
+            .field(
+                format!("参加する ({}人)", self.attendees.len()),
+                if attendees.is_empty() {
+                    "まだいません".to_string()
+                } else {
+                    attendees
+                },
+                false,
+            );
+
+        let mut components = CreateComponents::default();
+        components.create_action_row(|r| {
+            for (join, label, style) in [
+                (true, "参加する", ButtonStyle::Primary),
+                (false, "やめる", ButtonStyle::Secondary),
+            ] {
+                r.create_button(|b| {
+                    b.custom_id(GameNightCustomId {
+                        key: key.to_string(),
+                        join,
+                    })
+                    .label(label)
+                    .style(style)
+                });
+            }
+            r
+        });
+        (embed, components)
+    }
+
+    /// 開始時刻を過ぎたゲーム会について、参加者の共通のゲームを投稿する
+    ///
+    /// 投稿したゲーム会は削除する
+    pub async fn start_all(
+        ctx: &Context,
+        steam: &SteamApiClient,
+        steam_store: &StoreApiClient,
+        storage: &Storage,
+    ) -> Result<()> {
+        for key in storage.keys(Self::PERSIST_KEY_PREFIX)? {
+            let game_night = {
+                let _lock = LOCK.lock().unwrap();
+                let game_night = match Self::load(&key, storage) {
+                    Ok(game_night) => game_night,
+                    Err(LoadError::Missing) => continue,
+                    Err(e) => {
+                        tracing::warn!("{e:?}");
+                        storage.remove(&key)?;
+                        continue;
+                    }
+                };
+                if !game_night.is_started() {
+                    continue;
+                }
+                // 投稿に失敗しても、同じゲーム会を何度も投稿しないように先に削除する
+                storage.remove(&key)?;
+                game_night
+            };
+            let late = Timestamp::now().unix_timestamp() - game_night.starts_at.unix_timestamp();
+            if late > GRACE_PERIOD.as_secs() as i64 {
+                continue;
+            }
+            if let Err(e) = game_night
+                .start(ctx, &key, steam, steam_store, storage)
+                .await
+            {
+                tracing::warn!("{e:?}");
+            }
+        }
+        Ok(())
+    }
+
+    async fn start(
+        &self,
+        ctx: &Context,
+        key: &str,
+        steam: &SteamApiClient,
+        steam_store: &StoreApiClient,
+        storage: &Storage,
+    ) -> Result<()> {
+        // 募集を締め切る
+        if let Some(message_id) = self.message_id {
+            let (embed, _) = self.create_message(key);
+            if let Err(e) = self
+                .channel_id
+                .edit_message(ctx, message_id, |msg| {
+                    msg.set_embed(embed).components(|c| c)
+                })
+                .await
+            {
+                tracing::warn!("{e:?}");
+            }
+        }
+
+        let Some(guild) = self.guild_id.to_guild_cached(ctx) else {
+            return Ok(());
+        };
+        let members = fetch_members(ctx, &guild, self.attendees.iter().copied().collect()).await;
+        let (libraries, _) = load_libraries(members, steam, storage).await;
+        let read_users_count = libraries.len();
+        let hidden = HiddenGames::collect(
+            self.guild_id,
+            libraries.iter().map(|(member, _)| member.user_id),
+            storage,
+        );
+        let games = CommonGamesStore::new(
+            self.host,
+            gamenight::COMMAND,
+            Layout::new(None, false),
+            libraries,
+            &hidden,
+            &[],
+            steam_store,
+        )
+        .await;
+        // ゲーム会のキーと同じインタラクションの ID で保存する
+        let games_key = format!(
+            "{}{}",
+            CommonGamesStore::PERSIST_KEY_PREFIX,
+            &key[Self::PERSIST_KEY_PREFIX.len()..]
+        );
+        games.save(&games_key, storage)?;
+        let details = games.get_details(0, steam_store).await;
+        let (embeds, components) = create_page(&games_key, &games, 0, &details);
+
+        let mentions = self
+            .attendees
+            .iter()
+            .map(|user_id| format!("<@{user_id}>"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        self.channel_id
+            .send_message(ctx, |msg| {
+                msg.content(format!(
+                    "{mentions}\n「{}」の時間です！参加する{read_users_count}人の共通のゲームはこちらです。",
+                    self.title
+                ))
+                .set_embeds(embeds)
+                .set_components(components)
+            })
+            .await?;
+        Ok(())
+    }
+}
+
+impl Record for GameNight {
+    const VERSION: u32 = 1;
+}
+
+/// 参加するボタンとやめるボタンに設定するカスタムID
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GameNightCustomId {
+    /// ゲーム会のキー
+    pub key: String,
+    /// 参加するなら `true`、やめるなら `false`
+    pub join: bool,
+}
+
+impl FromStr for GameNightCustomId {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let id = serde_json::from_str(s)?;
+        Ok(id)
+    }
+}
+
+impl fmt::Display for GameNightCustomId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&serde_json::to_string(&self).expect("convert id to string error"))
+    }
+}
+
+/// 参加するボタンかやめるボタンが押されたときに、参加者を書き換える
+pub async fn handle_component(
+    ctx: impl AsRef<Http>,
+    component: &MessageComponentInteraction,
+    custom_id: GameNightCustomId,
+    storage: &Storage,
+) -> Result<()> {
+    let updated = GameNight::update(&custom_id.key, storage, |game_night| {
+        if custom_id.join {
+            game_night.attendees.insert(component.user.id);
+        } else {
+            game_night.attendees.remove(&component.user.id);
+        }
+    })?;
+    let game_night = match updated {
+        Some(game_night) => game_night,
+        None => {
+            component
+                .create_interaction_response(ctx, |response| {
+                    response
+                        .kind(InteractionResponseType::ChannelMessageWithSource)
+                        .interaction_response_data(|msg| {
+                            msg.ephemeral(true)
+                                .content("このゲーム会の募集は終わりました。")
+                        })
+                })
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let (embed, components) = game_night.create_message(&custom_id.key);
+    component
+        .create_interaction_response(ctx, |response| {
+            response
+                .kind(InteractionResponseType::UpdateMessage)
+                .interaction_response_data(|msg| msg.set_embed(embed).set_components(components))
+        })
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,408 @@
+//! 通話やサーバーにいるメンバーのライブラリをまとめて読む
+
+use std::collections::{HashMap, HashSet};
+
+use futures::future::join_all;
+use serenity::{
+    client::Context,
+    model::{
+        guild::Guild,
+        id::{ChannelId, UserId},
+    },
+};
+
+use crate::{
+    common_games::{AppId, Member},
+    library_sync::StoredLibrary,
+    steam::{Game, Library, OwnedGame, SteamApiClient},
+    storage::{LoadError, Storage},
+    user::User,
+};
+
+/// 通話チャンネルにいるメンバー
+pub fn voice_members(guild: &Guild, channel_id: ChannelId) -> Vec<Member> {
+    guild
+        .voice_states
+        .iter()
+        .filter(|(_, s)| s.channel_id == Some(channel_id))
+        .map(|(user_id, s)| {
+            let name = s
+                .member
+                .as_ref()
+                .or_else(|| guild.members.get(user_id))
+                .map(|m| m.display_name().into_owned())
+                .unwrap_or_else(|| user_id.to_string());
+            Member {
+                user_id: *user_id,
+                name,
+            }
+        })
+        .collect()
+}
+
+/// Steam ID を登録しているサーバーのメンバー
+///
+/// キャッシュにすべてのメンバーがいるときは API を呼ばない。
+/// そうでなければ、キャッシュにいない登録しているユーザーだけを API で問い合わせる
+pub async fn registered_members(
+    ctx: &Context,
+    guild: &Guild,
+    storage: &Storage,
+) -> anyhow::Result<Vec<Member>> {
+    let (cached, uncached): (Vec<_>, Vec<_>) = storage
+        .keys(User::PERSIST_KEY_PREFIX)?
+        .into_iter()
+        .filter_map(|key| key[User::PERSIST_KEY_PREFIX.len()..].parse().ok())
+        .map(UserId)
+        .partition(|user_id| guild.members.contains_key(user_id));
+    // キャッシュにいないユーザーは、ほかのサーバーのメンバーのことが多い
+    let complete = guild.members.len() as u64 >= guild.member_count;
+    let user_ids = if complete {
+        cached
+    } else {
+        cached.into_iter().chain(uncached).collect()
+    };
+    Ok(fetch_members(ctx, guild, user_ids).await)
+}
+
+/// `user_ids` のうちサーバーにいるメンバー
+///
+/// キャッシュにいないメンバーは API で問い合わせる
+pub async fn fetch_members(ctx: &Context, guild: &Guild, user_ids: Vec<UserId>) -> Vec<Member> {
+    let members = join_all(user_ids.into_iter().map(|user_id| async move {
+        if let Some(member) = guild.members.get(&user_id) {
+            return Some(member.clone());
+        }
+        // サーバーにいないユーザーはエラーになる
+        guild.id.member(ctx, user_id).await.ok()
+    }))
+    .await;
+    members
+        .into_iter()
+        .flatten()
+        .map(|member| Member {
+            user_id: member.user.id,
+            name: member.display_name().into_owned(),
+        })
+        .collect()
+}
+
+/// 登録しているメンバーとその Steam ID
+///
+/// 未登録のメンバーは除く
+pub fn load_users(members: Vec<Member>, storage: &Storage) -> Vec<(Member, User)> {
+    // Discord の ID から事前に登録された Steam の ID を引く
+    // 引けなかったものは存在しないものとして除外する
+    members
+        .into_iter()
+        .filter_map(
+            |member| match User::load(&member.user_id.to_string(), storage) {
+                Ok(user) => Some((member, user)),
+                Err(LoadError::Missing) => None,
+                Err(e @ LoadError::Corrupt(_)) => {
+                    tracing::warn!("{e:?}");
+                    None
+                }
+            },
+        )
+        .collect()
+}
+
+/// 登録しているメンバーのライブラリを読む
+///
+/// 未登録のメンバーは除く。ライブラリを読めなかったメンバーは 2 つ目に返す
+pub async fn load_libraries(
+    members: Vec<Member>,
+    steam: &SteamApiClient,
+    storage: &Storage,
+) -> (Vec<(Member, Library)>, Vec<Member>) {
+    load_libraries_with(members, steam, storage, false).await
+}
+
+/// `include_played_free_games` が `true` なら、一度でも遊んだ無料のゲームも含めてライブラリを読む
+pub async fn load_libraries_with(
+    members: Vec<Member>,
+    steam: &SteamApiClient,
+    storage: &Storage,
+    include_played_free_games: bool,
+) -> (Vec<(Member, Library)>, Vec<Member>) {
+    let users = load_users(members, storage);
+    let libraries =
+        join_all(users.iter().map(|(_, user)| {
+            steam.get_owned_games_with(user.steam_id(), include_played_free_games)
+        }))
+        .await;
+    let mut loaded = Vec::new();
+    let mut failed = Vec::new();
+    for ((member, _), library) in users.into_iter().zip(libraries) {
+        match library {
+            Ok(library) => loaded.push((member, library)),
+            Err(e) => {
+                tracing::warn!("{e:?}");
+                failed.push(member);
+            }
+        }
+    }
+    (loaded, failed)
+}
+
+/// 登録しているメンバーの保存しているライブラリを読む
+///
+/// まだ保存していないメンバーだけ取得する。未登録のメンバーは除き、ライブラリを読めなかったメンバーは 2 つ目に返す
+pub async fn load_stored_libraries_with(
+    members: Vec<Member>,
+    steam: &SteamApiClient,
+    storage: &Storage,
+    include_played_free_games: bool,
+) -> (Vec<(Member, Library)>, Vec<Member>) {
+    let users = load_users(members, storage);
+    let libraries = join_all(users.iter().map(|(member, user)| async move {
+        StoredLibrary::load_or_refresh(&member.user_id.to_string(), user, steam, storage).await
+    }))
+    .await;
+    let mut loaded = Vec::new();
+    let mut failed = Vec::new();
+    for ((member, _), library) in users.into_iter().zip(libraries) {
+        match library {
+            Ok(library) => loaded.push((member, library.library(include_played_free_games))),
+            Err(e) => {
+                tracing::warn!("{e:?}");
+                failed.push(member);
+            }
+        }
+    }
+    (loaded, failed)
+}
+
+/// 登録しているメンバーが直近 2 週間に遊んだゲームを読む
+///
+/// 未登録のメンバーは除く。読めなかったメンバーは 2 つ目に返す
+pub async fn load_recent_games(
+    members: Vec<Member>,
+    steam: &SteamApiClient,
+    storage: &Storage,
+) -> (Vec<(Member, Vec<OwnedGame>)>, Vec<Member>) {
+    let users = load_users(members, storage);
+    let recent = join_all(
+        users
+            .iter()
+            .map(|(_, user)| steam.get_recently_played_games(user.steam_id())),
+    )
+    .await;
+    let mut loaded = Vec::new();
+    let mut failed = Vec::new();
+    for ((member, _), recent) in users.into_iter().zip(recent) {
+        match recent {
+            Ok(recent) => loaded.push((member, recent)),
+            Err(e) => {
+                tracing::warn!("{e:?}");
+                failed.push(member);
+            }
+        }
+    }
+    (loaded, failed)
+}
+
+/// Steam ファミリーのほかのメンバーが持っているゲームを、借りられるものとしてライブラリに加える
+///
+/// 加えたゲームは [`OwnedGame::family_shared`] にしてプレイ時間は 0 にする。
+/// ファミリーのメンバーは通話にいなくてもよい
+pub async fn share_family_libraries(
+    libraries: &mut [(Member, Library)],
+    steam: &SteamApiClient,
+    storage: &Storage,
+    include_played_free_games: bool,
+) -> anyhow::Result<()> {
+    let users = User::load_all(storage)?;
+    for (member, library) in libraries.iter_mut() {
+        let Some((_, user)) = users
+            .iter()
+            .find(|(discord_id, _)| *discord_id == member.user_id.to_string())
+        else {
+            continue;
+        };
+        let Some(family) = user.family() else {
+            continue;
+        };
+        let relatives = users
+            .iter()
+            .filter(|(_, other)| other.family() == Some(family))
+            .filter(|(_, other)| other.steam_id() != user.steam_id())
+            .collect::<Vec<_>>();
+        let shared = join_all(relatives.iter().map(|(discord_id, other)| async move {
+            let stored = StoredLibrary::load_or_refresh(discord_id, other, steam, storage).await?;
+            anyhow::Ok(stored.library(include_played_free_games))
+        }))
+        .await;
+        for shared in shared {
+            let shared = match shared {
+                Ok(shared) => shared,
+                Err(e) => {
+                    tracing::warn!("{e:?}");
+                    continue;
+                }
+            };
+            for (appid, game) in shared {
+                library.entry(appid).or_insert_with(|| OwnedGame {
+                    game: game.game,
+                    playtime_forever: 0,
+                    playtime_2weeks: 0,
+                    family_shared: true,
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 登録しているメンバーのライブラリとウィッシュリストを読む
+///
+/// 未登録のメンバーと、どちらかを読めなかったメンバーは除く
+pub async fn load_libraries_and_wishlists(
+    members: Vec<Member>,
+    steam: &SteamApiClient,
+    storage: &Storage,
+) -> (Vec<(Member, Library)>, Vec<HashSet<AppId>>) {
+    let users = load_users(members, storage);
+    let fetched = join_all(users.iter().map(|(_, user)| async move {
+        let library = steam.get_owned_games(user.steam_id()).await?;
+        let wishlist = steam.get_wishlist(user.steam_id()).await?;
+        anyhow::Ok((library, wishlist))
+    }))
+    .await;
+    let mut libraries = Vec::new();
+    let mut wishlists = Vec::new();
+    for ((member, _), fetched) in users.into_iter().zip(fetched) {
+        match fetched {
+            Ok((library, wishlist)) => {
+                libraries.push((member, library));
+                wishlists.push(wishlist.into_iter().map(|item| item.appid).collect());
+            }
+            Err(e) => tracing::warn!("{e:?}"),
+        }
+    }
+    (libraries, wishlists)
+}
+
+/// 全員が持っているかウィッシュリストに入れていて、誰かがウィッシュリストに入れているゲーム
+///
+/// `wishlists` は `libraries` と同じ順に並べる
+pub fn common_wishlist(
+    libraries: &[(Member, Library)],
+    wishlists: &[HashSet<AppId>],
+    hidden: &HashSet<AppId>,
+) -> Vec<AppId> {
+    wishlists
+        .iter()
+        .flatten()
+        .copied()
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .filter(|appid| !hidden.contains(appid))
+        .filter(|appid| {
+            libraries
+                .iter()
+                .zip(wishlists)
+                .all(|((_, library), wishlist)| {
+                    library.contains_key(appid) || wishlist.contains(appid)
+                })
+        })
+        .collect()
+}
+
+/// 持っていない人が `max_missing` 人以内のアプリ
+///
+/// 持っていない人数が少なく、よく遊ばれているものから並べる。
+/// ゲームでないものも含むので、呼び出す側で [`StoreApiClient::filter_games`] で選ぶ
+///
+/// [`StoreApiClient::filter_games`]: crate::store::StoreApiClient::filter_games
+pub fn near_misses(
+    libraries: &[(Member, Library)],
+    hidden: &HashSet<AppId>,
+    max_missing: usize,
+) -> Vec<Game> {
+    let mut candidates = libraries
+        .iter()
+        .flat_map(|(_, library)| library.values())
+        .filter(|game| !hidden.contains(&game.game.appid))
+        .fold(HashMap::new(), |mut acc, game| {
+            let (_, owners, playtime) = acc
+                .entry(game.game.appid)
+                .or_insert_with(|| (game.game.clone(), 0, 0));
+            *owners += 1;
+            *playtime += game.playtime_forever;
+            acc
+        })
+        .into_values()
+        .filter(|(_, owners, _)| (1..=max_missing).contains(&(libraries.len() - owners)))
+        .collect::<Vec<_>>();
+    candidates.sort_by(|a, b| {
+        b.1.cmp(&a.1)
+            .then(b.2.cmp(&a.2))
+            .then(a.0.appid.cmp(&b.0.appid))
+    });
+    candidates.into_iter().map(|(game, _, _)| game).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `(appid, プレイ時間)` を持っているメンバーのライブラリ
+    fn library(user_id: u64, games: &[(AppId, u64)]) -> (Member, Library) {
+        let member = Member {
+            user_id: UserId(user_id),
+            name: user_id.to_string(),
+        };
+        let library = games
+            .iter()
+            .map(|&(appid, playtime_forever)| {
+                let game = OwnedGame {
+                    game: Game {
+                        appid,
+                        name: format!("Game {appid}"),
+                    },
+                    playtime_forever,
+                    playtime_2weeks: 0,
+                    family_shared: false,
+                };
+                (appid, game)
+            })
+            .collect();
+        (member, library)
+    }
+
+    #[test]
+    fn near_misses_orders_by_owners_then_playtime() {
+        let libraries = [
+            library(1, &[(10, 0), (20, 5), (30, 100), (40, 0), (50, 0)]),
+            library(2, &[(10, 0), (20, 5), (40, 0), (50, 0)]),
+            library(3, &[(10, 0), (30, 1), (40, 0)]),
+        ];
+        let hidden = HashSet::from([50]);
+        let appids = |max_missing| {
+            near_misses(&libraries, &hidden, max_missing)
+                .into_iter()
+                .map(|game| game.appid)
+                .collect::<Vec<_>>()
+        };
+        // 全員が持っている 10 と 40 は含めない
+        assert_eq!(appids(1), [30, 20]);
+        assert_eq!(appids(2), [30, 20]);
+        let libraries = [library(1, &[(10, 0)]), library(2, &[]), library(3, &[])];
+        assert_eq!(
+            near_misses(&libraries, &hidden, 1),
+            Vec::<Game>::new(),
+            "持っていない人が多すぎるものは含めない"
+        );
+    }
+
+    #[test]
+    fn common_wishlist_needs_everyone_to_own_or_want() {
+        let libraries = [library(1, &[(10, 0), (20, 0)]), library(2, &[(30, 0)])];
+        let wishlists = [HashSet::from([30, 40]), HashSet::from([10, 40, 50])];
+        let mut appids = common_wishlist(&libraries, &wishlists, &HashSet::from([40]));
+        appids.sort();
+        assert_eq!(appids, [10, 30]);
+    }
+}
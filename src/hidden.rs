@@ -0,0 +1,96 @@
+use std::collections::{BTreeMap, HashSet};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serenity::model::id::{GuildId, UserId};
+
+use crate::{
+    common_games::AppId,
+    storage::{LoadError, Record, Storage},
+};
+
+/// 非表示にするゲームのリストの持ち主
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum HideScope {
+    /// そのユーザーが参加する一覧から除く
+    User(UserId),
+    /// そのサーバーで作る一覧から除く
+    Guild(GuildId),
+}
+
+impl HideScope {
+    fn persist_key(&self) -> String {
+        match self {
+            HideScope::User(id) => format!("{}user-{id}", HiddenGames::PERSIST_KEY_PREFIX),
+            HideScope::Guild(id) => format!("{}guild-{id}", HiddenGames::PERSIST_KEY_PREFIX),
+        }
+    }
+}
+
+/// 共通ゲームの一覧に出さないゲーム
+#[derive(Serialize, Deserialize, Default, PartialEq, Eq, Clone, Debug)]
+pub struct HiddenGames {
+    /// 補完で名前を出せるように名前も一緒に持っておく
+    games: BTreeMap<AppId, String>,
+}
+
+impl HiddenGames {
+    /// 保存するときのキーの接頭辞
+    pub const PERSIST_KEY_PREFIX: &'static str = "hidden-games-";
+
+    /// まだ何も非表示にしていなければ空のリストになる
+    pub fn load(scope: HideScope, storage: &Storage) -> Result<HiddenGames, LoadError> {
+        match storage.load(&scope.persist_key()) {
+            Err(LoadError::Missing) => Ok(HiddenGames::default()),
+            result => result,
+        }
+    }
+
+    pub fn save(&self, scope: HideScope, storage: &Storage) -> Result<()> {
+        storage.save(&scope.persist_key(), self)
+    }
+
+    /// すでに非表示にしていれば `false` を返す
+    pub fn insert(&mut self, appid: AppId, name: String) -> bool {
+        self.games.insert(appid, name).is_none()
+    }
+
+    /// 非表示にしていなければ `None` を返す
+    pub fn remove(&mut self, appid: AppId) -> Option<String> {
+        self.games.remove(&appid)
+    }
+
+    pub fn appids(&self) -> impl Iterator<Item = AppId> + '_ {
+        self.games.keys().copied()
+    }
+
+    /// サーバーと参加しているメンバーが非表示にしているゲームをまとめる
+    ///
+    /// 読めなかったリストは無視する
+    pub fn collect(
+        guild_id: GuildId,
+        user_ids: impl IntoIterator<Item = UserId>,
+        storage: &Storage,
+    ) -> HashSet<AppId> {
+        let scopes = std::iter::once(HideScope::Guild(guild_id))
+            .chain(user_ids.into_iter().map(HideScope::User));
+        let mut appids = HashSet::new();
+        for scope in scopes {
+            match HiddenGames::load(scope, storage) {
+                Ok(hidden) => appids.extend(hidden.appids()),
+                Err(e) => tracing::warn!("{e:?}"),
+            }
+        }
+        appids
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (AppId, &str)> {
+        self.games
+            .iter()
+            .map(|(appid, name)| (*appid, name.as_str()))
+    }
+}
+
+impl Record for HiddenGames {
+    const VERSION: u32 = 1;
+}
@@ -0,0 +1,371 @@
+//! ゲームを持っている人だけが参加できる、一緒に遊ぶ人の募集
+
+use std::{collections::BTreeSet, fmt, str::FromStr, sync::Mutex, time::Duration};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serenity::{
+    builder::{CreateComponents, CreateEmbed},
+    client::Context,
+    model::{
+        application::{
+            component::ButtonStyle,
+            interaction::{
+                message_component::MessageComponentInteraction, InteractionResponseType,
+            },
+        },
+        id::{ChannelId, InteractionId, MessageId, UserId},
+        Timestamp,
+    },
+};
+
+use crate::{
+    common_games::{store_url, AppId},
+    steam::SteamApiClient,
+    storage::{LoadError, Record, Storage},
+    user::User,
+};
+
+/// 募集を締め切るまでの時間
+pub const LFG_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// 募集を読んでから保存か削除をするまでの間に、ほかの処理が書き換えないようにする
+///
+/// 同時に参加した人の書き換えが失われたり、締め切った募集を保存しなおしたりしないようにする
+static LOCK: Mutex<()> = Mutex::new(());
+
+const CLOSED_NOTICE: &str = "この募集は締め切られました。";
+
+/// 一緒に遊ぶ人の募集
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Lfg {
+    pub appid: AppId,
+    pub name: String,
+    /// 募集する人数 (募集した人は含まない)
+    pub slots: usize,
+    pub channel_id: ChannelId,
+    /// 募集のメッセージ
+    ///
+    /// 締め切ったらボタンを消す
+    pub message_id: Option<MessageId>,
+    /// 募集した人
+    pub host: UserId,
+    /// 参加した人 (募集した人は含まない)
+    pub members: BTreeSet<UserId>,
+    pub expires_at: Timestamp,
+}
+
+impl Lfg {
+    /// 保存するときのキーの接頭辞
+    pub const PERSIST_KEY_PREFIX: &'static str = "lfg-";
+
+    pub fn new(
+        appid: AppId,
+        name: String,
+        slots: usize,
+        channel_id: ChannelId,
+        host: UserId,
+    ) -> Lfg {
+        let expires_at = Timestamp::from_unix_timestamp(
+            Timestamp::now().unix_timestamp() + LFG_TTL.as_secs() as i64,
+        )
+        .unwrap_or_else(|_| Timestamp::now());
+        Lfg {
+            appid,
+            name,
+            slots,
+            channel_id,
+            message_id: None,
+            host,
+            members: BTreeSet::new(),
+            expires_at,
+        }
+    }
+
+    pub fn load(key: &str, storage: &Storage) -> Result<Lfg, LoadError> {
+        storage.load(key)
+    }
+
+    pub fn save(&self, key: &str, storage: &Storage) -> Result<()> {
+        storage.save(key, self)
+    }
+
+    /// 締め切っていない募集を読んで書き換え、保存する
+    ///
+    /// 読んでから保存するまでを順番に行う。メンバーが揃ったら保存せずに消す。
+    /// 締め切っていたときや `f` が断ったときは、代わりに表示するメッセージを返す
+    pub fn update(
+        key: &str,
+        storage: &Storage,
+        f: impl FnOnce(&mut Lfg) -> std::result::Result<(), &'static str>,
+    ) -> Result<std::result::Result<Lfg, &'static str>> {
+        let _lock = LOCK.lock().unwrap();
+        let mut lfg = match Self::load(key, storage) {
+            Ok(lfg) if !lfg.is_expired() => lfg,
+            _ => return Ok(Err(CLOSED_NOTICE)),
+        };
+        if let Err(notice) = f(&mut lfg) {
+            return Ok(Err(notice));
+        }
+        if lfg.is_full() {
+            storage.remove(key)?;
+        } else {
+            lfg.save(key, storage)?;
+        }
+        Ok(Ok(lfg))
+    }
+
+    /// 募集したコマンドのインタラクションの ID から保存するキーを作る
+    pub fn generate_persist_key(interaction_id: InteractionId) -> String {
+        format!("{}{interaction_id}", Self::PERSIST_KEY_PREFIX)
+    }
+
+    fn is_full(&self) -> bool {
+        self.members.len() >= self.slots
+    }
+
+    fn is_expired(&self) -> bool {
+        self.expires_at.unix_timestamp() <= Timestamp::now().unix_timestamp()
+    }
+
+    /// 募集のメッセージの埋め込みとボタン
+    ///
+    /// 締め切ったあとはボタンを付けない
+    pub fn create_message(&self, key: &str, closed: bool) -> (CreateEmbed, CreateComponents) {
+        let members = std::iter::once(&self.host)
+            .chain(&self.members)
+            .map(|user_id| format!("<@{user_id}>"))
+            .collect::<Vec<_>>()
+            .join("、");
+        let status = if !closed {
+            format!(
+                "あと{}人募集しています。<t:{}:R>に締め切ります。",
+                self.slots - self.members.len(),
+                self.expires_at.unix_timestamp()
+            )
+        } else if self.is_full() {
+            "メンバーが揃いました。".to_string()
+        } else {
+            "締め切りました。".to_string()
+        };
+        let mut embed = CreateEmbed::default();
+        embed
+            .title(format!("{} を一緒に遊ぶ人を募集", self.name))
+            .url(store_url(self.appid))
+            .description(format!(
+                "{status}\nこのゲームを持っている人だけが参加できます。"
+            ))
+            .field(
+                format!("メンバー ({}/{}人)", self.members.len() + 1, self.slots + 1),
+                members,
+                false,
+            );
+
+        let mut components = CreateComponents::default();
+        if !closed {
+            components.create_action_row(|r| {
+                for (join, label, style) in [
+                    (true, "参加する", ButtonStyle::Primary),
+                    (false, "やめる", ButtonStyle::Secondary),
+                ] {
+                    r.create_button(|b| {
+                        b.custom_id(LfgCustomId {
+                            key: key.to_string(),
+                            lfg_join: join,
+                        })
+                        .label(label)
+                        .style(style)
+                    });
+                }
+                r
+            });
+        }
+        (embed, components)
+    }
+
+    /// 期限が切れた募集を締め切る
+    pub async fn close_expired(ctx: &Context, storage: &Storage) -> Result<usize> {
+        let mut closed = 0;
+        for key in storage.keys(Self::PERSIST_KEY_PREFIX)? {
+            let lfg = {
+                let _lock = LOCK.lock().unwrap();
+                let lfg = match Self::load(&key, storage) {
+                    Ok(lfg) if lfg.is_expired() => lfg,
+                    Ok(_) | Err(LoadError::Missing) => continue,
+                    Err(e) => {
+                        tracing::warn!("{e:?}");
+                        storage.remove(&key)?;
+                        continue;
+                    }
+                };
+                storage.remove(&key)?;
+                lfg
+            };
+            closed += 1;
+            let Some(message_id) = lfg.message_id else {
+                continue;
+            };
+            let (embed, _) = lfg.create_message(&key, true);
+            if let Err(e) = lfg
+                .channel_id
+                .edit_message(ctx, message_id, |msg| {
+                    msg.set_embed(embed).components(|c| c)
+                })
+                .await
+            {
+                tracing::warn!("{e:?}");
+            }
+        }
+        Ok(closed)
+    }
+}
+
+impl Record for Lfg {
+    const VERSION: u32 = 1;
+}
+
+/// 参加するボタンとやめるボタンに設定するカスタムID
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LfgCustomId {
+    /// 募集のキー
+    pub key: String,
+    /// 参加するなら `true`、やめるなら `false`
+    ///
+    /// ゲーム会のボタンと区別できるように名前を変えている
+    pub lfg_join: bool,
+}
+
+impl FromStr for LfgCustomId {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let id = serde_json::from_str(s)?;
+        Ok(id)
+    }
+}
+
+impl fmt::Display for LfgCustomId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&serde_json::to_string(&self).expect("convert id to string error"))
+    }
+}
+
+/// 参加するボタンかやめるボタンが押されたときに、メンバーを書き換える
+///
+/// 参加できるのはゲームを持っている人だけ。揃ったら締め切って、メンバーを呼ぶ
+pub async fn handle_component(
+    ctx: &Context,
+    component: &MessageComponentInteraction,
+    custom_id: LfgCustomId,
+    steam: &SteamApiClient,
+    storage: &Storage,
+) -> Result<()> {
+    let user_id = component.user.id;
+    let lfg = match Lfg::load(&custom_id.key, storage) {
+        Ok(lfg) if !lfg.is_expired() => lfg,
+        _ => return respond_notice(ctx, component, CLOSED_NOTICE).await,
+    };
+
+    // ライブラリの取得を待つ間に募集が変わることがあるので、持っているかを先に確かめてから読みなおして書き換える
+    if custom_id.lfg_join {
+        if user_id == lfg.host || lfg.members.contains(&user_id) {
+            return respond_notice(ctx, component, "すでに参加しています。").await;
+        }
+        let owned = match User::load(&user_id.to_string(), storage) {
+            Ok(user) => match steam.get_owned_games(user.steam_id()).await {
+                Ok(library) => library.contains_key(&lfg.appid),
+                Err(e) => {
+                    tracing::warn!("{e:?}");
+                    return respond_notice(
+                        ctx,
+                        component,
+                        "ライブラリを読めなかったので、ゲームを持っているか確かめられませんでした。ライブラリの公開設定を確認するか、しばらくしてからもう一度試してください。",
+                    )
+                    .await;
+                }
+            },
+            Err(LoadError::Missing) => {
+                return respond_notice(
+                    ctx,
+                    component,
+                    "先に `/register` で Steam ID を登録してください。",
+                )
+                .await
+            }
+            Err(e @ LoadError::Corrupt(_)) => {
+                tracing::warn!("{e:?}");
+                return respond_notice(
+                    ctx,
+                    component,
+                    "登録されている情報を読み込めませんでした。もう一度 `/register` してください。",
+                )
+                .await;
+            }
+        };
+        if !owned {
+            return respond_notice(
+                ctx,
+                component,
+                "このゲームを持っている人だけが参加できます。",
+            )
+            .await;
+        }
+    } else if user_id == lfg.host {
+        return respond_notice(ctx, component, "募集した人はやめられません。").await;
+    }
+
+    let updated = Lfg::update(&custom_id.key, storage, |lfg| {
+        if !custom_id.lfg_join {
+            lfg.members.remove(&user_id);
+        } else if !lfg.members.insert(user_id) {
+            return Err("すでに参加しています。");
+        }
+        Ok(())
+    })?;
+    let lfg = match updated {
+        Ok(lfg) => lfg,
+        Err(notice) => return respond_notice(ctx, component, notice).await,
+    };
+    let full = lfg.is_full();
+
+    let (embed, components) = lfg.create_message(&custom_id.key, full);
+    component
+        .create_interaction_response(ctx, |response| {
+            response
+                .kind(InteractionResponseType::UpdateMessage)
+                .interaction_response_data(|msg| msg.set_embed(embed).set_components(components))
+        })
+        .await?;
+
+    if full {
+        let mentions = std::iter::once(&lfg.host)
+            .chain(&lfg.members)
+            .map(|user_id| format!("<@{user_id}>"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        lfg.channel_id
+            .say(
+                ctx,
+                format!(
+                    "{mentions}\n**{}** のメンバーが揃いました！\n起動: steam://run/{}",
+                    lfg.name, lfg.appid
+                ),
+            )
+            .await?;
+    }
+    Ok(())
+}
+
+async fn respond_notice(
+    ctx: &Context,
+    component: &MessageComponentInteraction,
+    notice: &str,
+) -> Result<()> {
+    component
+        .create_interaction_response(ctx, |response| {
+            response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|msg| msg.ephemeral(true).content(notice))
+        })
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,600 @@
+mod achievements;
+mod app_index;
+mod cache;
+mod commands;
+mod common_games;
+pub mod config;
+mod free_games;
+mod game_night;
+mod group;
+mod hidden;
+mod lfg;
+mod library_sync;
+pub mod migrate;
+mod playtime;
+mod sale_alerts;
+pub mod steam;
+pub mod storage;
+mod store;
+mod user;
+
+use std::{
+    str::FromStr,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use anyhow::Result;
+use app_index::AppIndex;
+use futures::future::join_all;
+use serenity::model::prelude::*;
+use serenity::prelude::*;
+use serenity::{async_trait, model::prelude::command::Command};
+use steam::SteamApiClient;
+use storage::Storage;
+use store::StoreApiClient;
+use tracing::{error, info};
+
+use crate::achievements::{AchievementsCustomId, AchievementsStore};
+use crate::common_games::{CommonGamesCustomId, CommonGamesStore};
+use crate::config::Config;
+use crate::game_night::{GameNight, GameNightCustomId};
+use crate::lfg::{Lfg, LfgCustomId};
+use crate::library_sync::{RefreshSchedule, REFRESH_TICK};
+use crate::playtime::PlaytimeHistory;
+use crate::sale_alerts::SaleAlerts;
+
+/// 期限切れの共通ゲームや実績の一覧を削除する間隔
+const GC_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// セールの通知のために価格を調べる間隔
+///
+/// ストアの情報のキャッシュと同じくらいにしておく
+const SALE_CHECK_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+
+/// 起動してから最初にセールを確認するまでの時間
+const SALE_CHECK_DELAY: Duration = Duration::from_secs(60);
+
+/// 週ごとのプレイ時間の増え方のために、プレイ時間を記録する間隔
+const PLAYTIME_RECORD_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// 開始時刻を過ぎたゲーム会を確認する間隔
+const GAME_NIGHT_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// 期限が切れた募集を締め切る間隔
+const LFG_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Steam のアプリの一覧を取得しなおす間隔
+const APP_LIST_REFRESH_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+pub struct Bot {
+    steam: SteamApiClient,
+    steam_store: StoreApiClient,
+    app_index: AppIndex,
+    storage: Storage,
+    common_games_ttl: Duration,
+    /// `ready` は再接続のたびに呼ばれるので、定期的な処理を一度だけ始めるためのフラグ
+    tasks_started: AtomicBool,
+}
+
+impl Bot {
+    pub fn new(config: &Config, storage: Storage) -> Bot {
+        Bot {
+            steam: SteamApiClient::new(config.steam_api_key.clone()),
+            steam_store: StoreApiClient::new(storage.clone()),
+            app_index: AppIndex::new(),
+            storage,
+            common_games_ttl: config.common_games_ttl,
+            tasks_started: AtomicBool::new(false),
+        }
+    }
+
+    /// 期限切れの共通ゲームの一覧を定期的に削除する
+    fn spawn_gc(&self) {
+        let storage = self.storage.clone();
+        let ttl = self.common_games_ttl;
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(GC_INTERVAL);
+            loop {
+                interval.tick().await;
+                match CommonGamesStore::collect_garbage(&storage, ttl) {
+                    Ok(removed) => info!("Removed {removed} expired common games"),
+                    Err(e) => error!("{e:?}"),
+                }
+                match AchievementsStore::collect_garbage(&storage, ttl) {
+                    Ok(removed) => info!("Removed {removed} expired achievements"),
+                    Err(e) => error!("{e:?}"),
+                }
+            }
+        });
+    }
+
+    /// Steam のアプリの一覧を定期的に取得しなおす
+    fn spawn_app_list_refresh(&self) {
+        let index = self.app_index.clone();
+        let steam = self.steam.clone();
+        let storage = self.storage.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(APP_LIST_REFRESH_INTERVAL);
+            loop {
+                interval.tick().await;
+                if let Err(e) = index
+                    .refresh(&steam, &storage, APP_LIST_REFRESH_INTERVAL)
+                    .await
+                {
+                    error!("{e:?}");
+                }
+            }
+        });
+    }
+
+    /// 登録しているユーザーのプレイ時間を定期的に記録する
+    ///
+    /// 起動したときにもすぐ記録するが、前回の記録から 1 日経っていないユーザーは飛ばす
+    fn spawn_playtime_records(&self) {
+        let storage = self.storage.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(PLAYTIME_RECORD_INTERVAL);
+            loop {
+                interval.tick().await;
+                match PlaytimeHistory::record_all(&storage) {
+                    Ok(recorded) => info!("Recorded playtime of {recorded} users"),
+                    Err(e) => error!("{e:?}"),
+                }
+            }
+        });
+    }
+
+    /// 開始時刻を過ぎたゲーム会に参加者の共通のゲームを投稿する
+    ///
+    /// ゲーム会は保存しているので、再起動しても続きから確認する
+    fn spawn_game_nights(&self, ctx: Context) {
+        let steam = self.steam.clone();
+        let steam_store = self.steam_store.clone();
+        let storage = self.storage.clone();
+        tokio::spawn(async move {
+            // `ready` の時点ではサーバーの情報がまだキャッシュに入っていないので少し待つ
+            let start = tokio::time::Instant::now() + GAME_NIGHT_CHECK_INTERVAL;
+            let mut interval = tokio::time::interval_at(start, GAME_NIGHT_CHECK_INTERVAL);
+            loop {
+                interval.tick().await;
+                if let Err(e) = GameNight::start_all(&ctx, &steam, &steam_store, &storage).await {
+                    error!("{e:?}");
+                }
+            }
+        });
+    }
+
+    /// 登録しているユーザーのライブラリを 1 人ずつ順番に取得しなおして保存する
+    ///
+    /// 全員を一度に取得すると API の制限にかかるので、少しずつ間を空ける
+    fn spawn_library_refresh(&self) {
+        let steam = self.steam.clone();
+        let storage = self.storage.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(REFRESH_TICK);
+            let mut schedule = RefreshSchedule::default();
+            loop {
+                interval.tick().await;
+                if let Err(e) = schedule.refresh_next(&steam, &storage).await {
+                    error!("{e:?}");
+                }
+            }
+        });
+    }
+
+    /// 期限が切れた `/lfg` の募集を締め切る
+    fn spawn_lfg_expiry(&self, ctx: Context) {
+        let storage = self.storage.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(LFG_CHECK_INTERVAL);
+            loop {
+                interval.tick().await;
+                if let Err(e) = Lfg::close_expired(&ctx, &storage).await {
+                    error!("{e:?}");
+                }
+            }
+        });
+    }
+
+    /// セールの通知を定期的に確認する
+    ///
+    /// 通知を投稿するために Discord のクライアントを使う
+    fn spawn_sale_alerts(&self, ctx: Context) {
+        let steam = self.steam.clone();
+        let steam_store = self.steam_store.clone();
+        let storage = self.storage.clone();
+        tokio::spawn(async move {
+            // `ready` の時点ではサーバーの情報がまだキャッシュに入っていないので少し待つ
+            let start = tokio::time::Instant::now() + SALE_CHECK_DELAY;
+            let mut interval = tokio::time::interval_at(start, SALE_CHECK_INTERVAL);
+            loop {
+                interval.tick().await;
+                if let Err(e) = SaleAlerts::check_all(&ctx, &steam, &steam_store, &storage).await {
+                    error!("{e:?}");
+                }
+            }
+        });
+    }
+}
+
+#[async_trait]
+impl EventHandler for Bot {
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        match interaction {
+            Interaction::ApplicationCommand(command) => {
+                let resp = match command.data.name.as_str() {
+                    commands::register::COMMAND => {
+                        commands::register::run(ctx.clone(), &command, &self.storage).await
+                    }
+                    commands::show::COMMAND => {
+                        commands::show::run(ctx.clone(), &command, &self.storage).await
+                    }
+                    commands::get_common_games::COMMAND => {
+                        commands::get_common_games::run(
+                            ctx.clone(),
+                            &command,
+                            &self.steam,
+                            &self.steam_store,
+                            &self.storage,
+                        )
+                        .await
+                    }
+                    commands::common_wishlist::COMMAND => {
+                        commands::common_wishlist::run(
+                            ctx.clone(),
+                            &command,
+                            &self.steam,
+                            &self.steam_store,
+                            &self.app_index,
+                            &self.storage,
+                        )
+                        .await
+                    }
+                    commands::near_misses::COMMAND => {
+                        commands::near_misses::run(
+                            ctx.clone(),
+                            &command,
+                            &self.steam,
+                            &self.steam_store,
+                            &self.storage,
+                        )
+                        .await
+                    }
+                    commands::now_playing::COMMAND => {
+                        commands::now_playing::run(
+                            ctx.clone(),
+                            &command,
+                            &self.steam,
+                            &self.app_index,
+                            &self.storage,
+                        )
+                        .await
+                    }
+                    commands::recent::COMMAND => {
+                        commands::recent::run(
+                            ctx.clone(),
+                            &command,
+                            &self.steam,
+                            &self.steam_store,
+                            &self.storage,
+                        )
+                        .await
+                    }
+                    commands::sale_alerts::COMMAND => {
+                        commands::sale_alerts::run(ctx.clone(), &command, &self.storage).await
+                    }
+                    commands::hide_game::COMMAND => {
+                        commands::hide_game::run(
+                            ctx.clone(),
+                            &command,
+                            &self.steam,
+                            &self.steam_store,
+                            &self.app_index,
+                            &self.storage,
+                        )
+                        .await
+                    }
+                    commands::unhide_game::COMMAND => {
+                        commands::unhide_game::run(ctx.clone(), &command, &self.storage).await
+                    }
+                    commands::who_owns::COMMAND => {
+                        commands::who_owns::run(
+                            &ctx,
+                            &command,
+                            &self.steam,
+                            &self.steam_store,
+                            &self.app_index,
+                            &self.storage,
+                        )
+                        .await
+                    }
+                    commands::achievements::COMMAND => {
+                        commands::achievements::run(
+                            ctx.clone(),
+                            &command,
+                            &self.steam,
+                            &self.steam_store,
+                            &self.app_index,
+                            &self.storage,
+                        )
+                        .await
+                    }
+                    commands::leaderboard::COMMAND => {
+                        commands::leaderboard::run(
+                            &ctx,
+                            &command,
+                            &self.steam,
+                            &self.steam_store,
+                            &self.app_index,
+                            &self.storage,
+                        )
+                        .await
+                    }
+                    commands::gamenight::COMMAND => {
+                        commands::gamenight::run(ctx.clone(), &command, &self.storage).await
+                    }
+                    commands::lfg::COMMAND => {
+                        commands::lfg::run(
+                            &ctx,
+                            &command,
+                            &self.steam,
+                            &self.steam_store,
+                            &self.app_index,
+                            &self.storage,
+                        )
+                        .await
+                    }
+                    commands::lfg_notify::COMMAND => {
+                        commands::lfg_notify::run(ctx.clone(), &command, &self.storage).await
+                    }
+                    commands::family::COMMAND => {
+                        commands::family::run(ctx.clone(), &command, &self.storage).await
+                    }
+                    commands::help::COMMAND => commands::help::run(ctx.clone(), &command).await,
+                    c => {
+                        tracing::warn!("Not implimented {c}");
+                        return;
+                    }
+                };
+                if let Err(e) = resp {
+                    tracing::warn!("{e:?}");
+                }
+            }
+            Interaction::MessageComponent(component) => {
+                if let Ok(custom_id) = CommonGamesCustomId::from_str(&component.data.custom_id) {
+                    if let Err(e) = common_games::handle_component(
+                        &ctx,
+                        &component,
+                        custom_id,
+                        &self.storage,
+                        &self.steam,
+                        &self.steam_store,
+                        self.common_games_ttl,
+                    )
+                    .await
+                    {
+                        tracing::error!("{e:?}")
+                    }
+                } else if let Ok(custom_id) = GameNightCustomId::from_str(&component.data.custom_id)
+                {
+                    if let Err(e) =
+                        game_night::handle_component(&ctx, &component, custom_id, &self.storage)
+                            .await
+                    {
+                        tracing::error!("{e:?}")
+                    }
+                } else if let Ok(custom_id) = LfgCustomId::from_str(&component.data.custom_id) {
+                    if let Err(e) = lfg::handle_component(
+                        &ctx,
+                        &component,
+                        custom_id,
+                        &self.steam,
+                        &self.storage,
+                    )
+                    .await
+                    {
+                        tracing::error!("{e:?}")
+                    }
+                } else if let Ok(custom_id) =
+                    AchievementsCustomId::from_str(&component.data.custom_id)
+                {
+                    if let Err(e) = achievements::handle_component(
+                        &ctx,
+                        &component,
+                        custom_id,
+                        &self.storage,
+                        self.common_games_ttl,
+                    )
+                    .await
+                    {
+                        tracing::error!("{e:?}")
+                    }
+                } else {
+                    // 形式を変える前に作った一覧のボタンなど、もう扱えないもの
+                    if let Err(e) = component
+                        .create_interaction_response(&ctx, |response| {
+                            response
+                                .kind(InteractionResponseType::ChannelMessageWithSource)
+                                .interaction_response_data(|msg| {
+                                    msg.ephemeral(true).content(common_games::EXPIRED_NOTICE)
+                                })
+                        })
+                        .await
+                    {
+                        tracing::error!("{e:?}")
+                    }
+                }
+            }
+            Interaction::ModalSubmit(modal) => {
+                if let Ok(custom_id) = CommonGamesCustomId::from_str(&modal.data.custom_id) {
+                    if let Err(e) = common_games::handle_modal_submit(
+                        &ctx,
+                        &modal,
+                        custom_id,
+                        &self.storage,
+                        &self.steam_store,
+                        self.common_games_ttl,
+                    )
+                    .await
+                    {
+                        tracing::error!("{e:?}")
+                    }
+                }
+            }
+            Interaction::Autocomplete(autocomplete) => {
+                let resp = match autocomplete.data.name.as_str() {
+                    commands::get_common_games::COMMAND => {
+                        commands::get_common_games::autocomplete(
+                            &ctx,
+                            &autocomplete,
+                            &self.steam,
+                            &self.storage,
+                        )
+                        .await
+                    }
+                    commands::hide_game::COMMAND => {
+                        commands::hide_game::autocomplete(
+                            &ctx,
+                            &autocomplete,
+                            &self.steam,
+                            &self.steam_store,
+                            &self.app_index,
+                            &self.storage,
+                        )
+                        .await
+                    }
+                    commands::unhide_game::COMMAND => {
+                        commands::unhide_game::autocomplete(&ctx, &autocomplete, &self.storage)
+                            .await
+                    }
+                    commands::achievements::COMMAND => {
+                        commands::achievements::autocomplete(
+                            &ctx,
+                            &autocomplete,
+                            &self.steam,
+                            &self.steam_store,
+                            &self.app_index,
+                            &self.storage,
+                        )
+                        .await
+                    }
+                    commands::leaderboard::COMMAND => {
+                        commands::leaderboard::autocomplete(
+                            &ctx,
+                            &autocomplete,
+                            &self.steam,
+                            &self.steam_store,
+                            &self.app_index,
+                            &self.storage,
+                        )
+                        .await
+                    }
+                    commands::lfg::COMMAND => {
+                        commands::lfg::autocomplete(
+                            &ctx,
+                            &autocomplete,
+                            &self.steam,
+                            &self.steam_store,
+                            &self.app_index,
+                            &self.storage,
+                        )
+                        .await
+                    }
+                    commands::who_owns::COMMAND => {
+                        commands::who_owns::autocomplete(
+                            &ctx,
+                            &autocomplete,
+                            &self.steam,
+                            &self.steam_store,
+                            &self.app_index,
+                            &self.storage,
+                        )
+                        .await
+                    }
+                    _ => return,
+                };
+                if let Err(e) = resp {
+                    tracing::warn!("{e:?}");
+                }
+            }
+            _ => {}
+        }
+    }
+
+    async fn ready(&self, ctx: Context, ready: Ready) {
+        info!("{} is connected!", ready.user.name);
+
+        if !self.tasks_started.swap(true, Ordering::SeqCst) {
+            self.spawn_gc();
+            self.spawn_app_list_refresh();
+            self.spawn_sale_alerts(ctx.clone());
+            self.spawn_playtime_records();
+            self.spawn_game_nights(ctx.clone());
+            self.spawn_lfg_expiry(ctx.clone());
+            self.spawn_library_refresh();
+        }
+
+        // 登録する前に先に古いコマンドを一通り削除する
+        if let Ok(commands) = Command::get_global_application_commands(&ctx).await {
+            if let Some(err) = join_all(
+                commands
+                    .into_iter()
+                    .map(|command| Command::delete_global_application_command(&ctx, command.id)),
+            )
+            .await
+            .into_iter()
+            .find_map(|r| r.err())
+            {
+                tracing::error!("{err:?}");
+            }
+            tracing::info!("Remove older commands");
+        }
+
+        for register in [
+            commands::show::register,
+            commands::register::register,
+            commands::get_common_games::register,
+            commands::common_wishlist::register,
+            commands::near_misses::register,
+            commands::recent::register,
+            commands::now_playing::register,
+            commands::sale_alerts::register,
+            commands::hide_game::register,
+            commands::unhide_game::register,
+            commands::who_owns::register,
+            commands::achievements::register,
+            commands::leaderboard::register,
+            commands::gamenight::register,
+            commands::lfg::register,
+            commands::lfg_notify::register,
+            commands::family::register,
+            commands::help::register,
+        ] {
+            if let Err(e) =
+                Command::create_global_application_command(&ctx, |command| register(command)).await
+            {
+                error!("{e:?}");
+            }
+        }
+    }
+}
+
+/// shuttle と単体の両方から使う Discord のクライアントを作る
+pub async fn create_client(config: &Config, storage: Storage) -> Result<Client> {
+    // Set gateway intents, which decides what events the bot will be notified about
+    let mut intents = GatewayIntents::non_privileged()
+        | GatewayIntents::GUILD_MESSAGES
+        | GatewayIntents::MESSAGE_CONTENT;
+    // 特権のプレゼンスは有効にしていないと接続できないので、設定されたときだけ受け取る
+    // 受け取らないときは、`/now-playing` は Steam のプロフィールだけから遊んでいるゲームを読む
+    if config.presence_intent {
+        intents |= GatewayIntents::GUILD_PRESENCES;
+    }
+
+    let client = Client::builder(&config.discord_token, intents)
+        .event_handler(Bot::new(config, storage))
+        .await?;
+
+    Ok(client)
+}
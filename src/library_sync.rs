@@ -0,0 +1,263 @@
+//! 登録しているユーザーのライブラリを少しずつ取得しなおして保存しておく処理
+//!
+//! コマンドを呼ばれたときに API を待たずに、保存しているライブラリから答える
+
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serenity::model::Timestamp;
+
+use crate::{
+    common_games::AppId,
+    steam::{Library, SteamApiClient},
+    storage::{LoadError, Record, Storage},
+    user::User,
+};
+
+/// 1 人のライブラリを取得しなおす間隔
+const REFRESH_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+
+/// 取得しなおすユーザーを選ぶ間隔
+///
+/// 1 回に 1 人だけ取得するので、API を呼ぶのは多くても 1 分に 2 回になる
+pub const REFRESH_TICK: Duration = Duration::from_secs(60);
+
+/// 新しく増えたゲームを残しておく期間
+const NEW_GAMES_RETENTION: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// 前回取得したときから新しく増えたゲーム
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct NewGame {
+    pub appid: AppId,
+    pub name: String,
+    pub found_at: Timestamp,
+}
+
+/// ユーザーごとに保存しているライブラリ
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct StoredLibrary {
+    /// 取得したときに登録されていた Steam ID
+    ///
+    /// 登録しなおして変わっていたら取得しなおす
+    steam_id: String,
+    fetched_at: Timestamp,
+    /// 一度でも遊んだ無料のゲームも含む
+    games: Library,
+    /// `games` のうち、一度でも遊んだ無料のゲーム
+    played_free: HashSet<AppId>,
+    /// 古い順
+    new_games: Vec<NewGame>,
+}
+
+impl StoredLibrary {
+    /// 保存するときのキーの接頭辞
+    pub const PERSIST_KEY_PREFIX: &'static str = "library-";
+
+    pub fn load(discord_id: &str, storage: &Storage) -> Result<StoredLibrary, LoadError> {
+        storage.load(&Self::generate_persist_key(discord_id))
+    }
+
+    pub fn save(&self, discord_id: &str, storage: &Storage) -> Result<()> {
+        storage.save(&Self::generate_persist_key(discord_id), self)
+    }
+
+    fn generate_persist_key(discord_id: &str) -> String {
+        format!("{}{discord_id}", Self::PERSIST_KEY_PREFIX)
+    }
+
+    /// `include_played_free_games` が `true` なら、一度でも遊んだ無料のゲームも含める
+    pub fn library(&self, include_played_free_games: bool) -> Library {
+        self.games
+            .iter()
+            .filter(|(appid, _)| include_played_free_games || !self.played_free.contains(appid))
+            .map(|(appid, game)| (*appid, game.clone()))
+            .collect()
+    }
+
+    /// 直近 30 日に新しく増えたゲーム (古い順)
+    pub fn new_games(&self) -> &[NewGame] {
+        &self.new_games
+    }
+
+    /// 取得したときに登録されていた Steam ID
+    pub fn steam_id(&self) -> &str {
+        &self.steam_id
+    }
+
+    /// ライブラリを取得した時刻
+    pub fn fetched_at(&self) -> Timestamp {
+        self.fetched_at
+    }
+
+    fn is_stale(&self, user: &User) -> bool {
+        is_stale(&self.steam_id, self.fetched_at, user)
+    }
+
+    /// 保存しているライブラリを読む
+    ///
+    /// まだ保存していないか、Steam ID が変わっていたり古くなっていたりしたら、取得して保存する。
+    /// 古くなっていただけなら、取得できなかったときは保存しているライブラリを返す
+    pub async fn load_or_refresh(
+        discord_id: &str,
+        user: &User,
+        steam: &SteamApiClient,
+        storage: &Storage,
+    ) -> Result<StoredLibrary> {
+        let stored = match Self::load(discord_id, storage) {
+            Ok(stored) if !stored.is_stale(user) => return Ok(stored),
+            Ok(stored) if stored.steam_id == user.steam_id() => Some(stored),
+            Ok(_) | Err(LoadError::Missing) => None,
+            Err(e) => {
+                tracing::warn!("{e:?}");
+                None
+            }
+        };
+        match (
+            Self::refresh(discord_id, user, steam, storage).await,
+            stored,
+        ) {
+            (Err(e), Some(stored)) => {
+                tracing::warn!("{e:?}");
+                Ok(stored)
+            }
+            (result, _) => result,
+        }
+    }
+
+    /// ライブラリを取得しなおして、前回から増えたゲームを記録する
+    ///
+    /// キャッシュは使わずに取得する
+    pub async fn refresh(
+        discord_id: &str,
+        user: &User,
+        steam: &SteamApiClient,
+        storage: &Storage,
+    ) -> Result<StoredLibrary> {
+        let games = steam.fetch_owned_games(user.steam_id(), true).await?;
+        let paid = steam.fetch_owned_games(user.steam_id(), false).await?;
+        let played_free = games
+            .keys()
+            .filter(|appid| !paid.contains_key(appid))
+            .copied()
+            .collect();
+
+        let now = Timestamp::now();
+        let mut new_games = Vec::new();
+        // 初めて取得したときや Steam ID が変わったときは、すべてのゲームが増えたことになるので記録しない
+        match Self::load(discord_id, storage) {
+            Ok(previous) if previous.steam_id == user.steam_id() => {
+                let threshold = now.unix_timestamp() - NEW_GAMES_RETENTION.as_secs() as i64;
+                new_games = previous.new_games;
+                new_games.retain(|game| game.found_at.unix_timestamp() >= threshold);
+                let mut found = paid
+                    .values()
+                    .filter(|game| !previous.games.contains_key(&game.game.appid))
+                    .map(|game| NewGame {
+                        appid: game.game.appid,
+                        name: game.game.name.clone(),
+                        found_at: now,
+                    })
+                    .collect::<Vec<_>>();
+                if !found.is_empty() {
+                    tracing::info!("{discord_id} has {} new games", found.len());
+                }
+                found.sort_by_key(|game| game.appid);
+                new_games.extend(found);
+            }
+            Ok(_) | Err(LoadError::Missing) => {}
+            Err(e) => tracing::warn!("{e:?}"),
+        }
+
+        let stored = StoredLibrary {
+            steam_id: user.steam_id().to_string(),
+            fetched_at: now,
+            games,
+            played_free,
+            new_games,
+        };
+        stored.save(discord_id, storage)?;
+        Ok(stored)
+    }
+}
+
+impl Record for StoredLibrary {
+    const VERSION: u32 = 1;
+}
+
+/// 登録されている Steam ID が変わったか、取得しなおす時期か
+fn is_stale(steam_id: &str, fetched_at: Timestamp, user: &User) -> bool {
+    let threshold = Timestamp::now().unix_timestamp() - REFRESH_INTERVAL.as_secs() as i64;
+    steam_id != user.steam_id() || fetched_at.unix_timestamp() <= threshold
+}
+
+/// ライブラリを取得しなおす順番と、ユーザーごとに取得した時刻
+///
+/// 取得しなおすユーザーを選ぶたびにすべてのライブラリを読まないように、取得した時刻をメモリに覚えておく
+#[derive(Default)]
+pub struct RefreshSchedule {
+    /// Discord の ID ごとの、取得したときの Steam ID と時刻
+    fetched: HashMap<String, (String, Timestamp)>,
+    /// 最後に取得しなおしたユーザーの Discord の ID
+    last: Option<String>,
+}
+
+impl RefreshSchedule {
+    /// 前回のユーザーの次から順に見て、取得しなおす時期のユーザーを 1 人だけ取得しなおす
+    ///
+    /// 取得できないユーザーがいても止まらないように、ユーザーを順番に回る。
+    /// 取得しなおしたユーザーの Discord の ID を返し、いなければ `None` を返す
+    pub async fn refresh_next(
+        &mut self,
+        steam: &SteamApiClient,
+        storage: &Storage,
+    ) -> Result<Option<String>> {
+        let mut users = User::load_all(storage)?;
+        users.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let split = users
+            .iter()
+            .position(|(discord_id, _)| Some(discord_id.as_str()) > self.last.as_deref())
+            .unwrap_or(users.len());
+        users.rotate_left(split);
+        let Some((discord_id, user)) = users
+            .into_iter()
+            .find(|(discord_id, user)| self.is_due(discord_id, user, storage))
+        else {
+            return Ok(None);
+        };
+        match StoredLibrary::refresh(&discord_id, &user, steam, storage).await {
+            Ok(stored) => self.remember(&discord_id, &stored),
+            Err(e) => tracing::warn!("{e:?}"),
+        }
+        self.last = Some(discord_id.clone());
+        Ok(Some(discord_id))
+    }
+
+    /// 取得しなおす時期か
+    ///
+    /// コマンドから取得したときの時刻は覚えていないので、覚えている時刻で時期が来ていたら保存しているライブラリで確かめる
+    fn is_due(&mut self, discord_id: &str, user: &User, storage: &Storage) -> bool {
+        if let Some((steam_id, fetched_at)) = self.fetched.get(discord_id) {
+            if !is_stale(steam_id, *fetched_at, user) {
+                return false;
+            }
+        }
+        match StoredLibrary::load(discord_id, storage) {
+            Ok(stored) => {
+                self.remember(discord_id, &stored);
+                stored.is_stale(user)
+            }
+            Err(_) => true,
+        }
+    }
+
+    fn remember(&mut self, discord_id: &str, stored: &StoredLibrary) {
+        self.fetched.insert(
+            discord_id.to_string(),
+            (stored.steam_id.clone(), stored.fetched_at),
+        );
+    }
+}
@@ -0,0 +1,272 @@
+//! shuttle-persist に保存されたデータを新しい [`Storage`] に移行する
+//!
+//! shuttle-persist は `shuttle_persist/{サービス名}/{key}.bin` に bincode で保存しているので、
+//! そのディレクトリを直接読んで、スキーマのバージョンをつけた形式に変換する。
+//! 移行先にすでに同じ内容があるものは飛ばすので、何度実行しても結果は変わらない。
+
+use std::{fmt, fs, path::Path};
+
+use anyhow::{Context, Result};
+
+use crate::{
+    common_games::CommonGamesStore,
+    storage::{LoadError, Record, Storage},
+    user::User,
+};
+
+/// 移行の結果
+#[derive(Default, Debug)]
+pub struct MigrationReport {
+    /// 新しく書き込んだキー
+    pub migrated: Vec<String>,
+    /// 移行先に同じ内容があったキー
+    pub unchanged: Vec<String>,
+    /// 移行先に異なる内容があったキー
+    pub conflicts: Vec<String>,
+    /// 移行する必要のないキー
+    pub skipped: Vec<String>,
+    /// 移行せずに捨てた共通ゲームの一覧のキー
+    ///
+    /// 一覧は操作したメッセージのキーで探すので、移行しても開くことができない
+    pub discarded: Vec<String>,
+    /// 読めなかったキーとその理由
+    pub failed: Vec<(String, String)>,
+}
+
+impl fmt::Display for MigrationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "migrated: {}, unchanged: {}, conflicts: {}, skipped: {}, discarded: {}, failed: {}",
+            self.migrated.len(),
+            self.unchanged.len(),
+            self.conflicts.len(),
+            self.skipped.len(),
+            self.discarded.len(),
+            self.failed.len()
+        )?;
+        for key in &self.conflicts {
+            writeln!(f, "conflict: {key}")?;
+        }
+        for (key, reason) in &self.failed {
+            writeln!(f, "failed: {key}: {reason}")?;
+        }
+        Ok(())
+    }
+}
+
+/// 移行先に異なる内容があったときの扱い
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OnConflict {
+    /// 移行先をそのまま残す
+    Skip,
+    /// 移行元の内容で上書きする
+    Overwrite,
+}
+
+/// `source` にある shuttle-persist のデータを `target` に移行する
+///
+/// 共通ゲームの一覧は使い捨てなので移行せずに [`MigrationReport::discarded`] に記録する
+pub fn migrate(
+    source: &Path,
+    target: &Storage,
+    on_conflict: OnConflict,
+) -> Result<MigrationReport> {
+    let mut report = MigrationReport::default();
+
+    let mut entries = fs::read_dir(source)
+        .with_context(|| format!("failed to read {}", source.display()))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort();
+
+    for path in entries {
+        if path.extension().and_then(|ext| ext.to_str()) != Some("bin") {
+            continue;
+        }
+        let Some(key) = path.file_stem().and_then(|stem| stem.to_str()) else {
+            continue;
+        };
+
+        // キーの形からデータの型を判断する
+        // - `discord-user-*` はユーザー
+        // - 数字だけのものは呼び出したユーザーの ID で保存された共通ゲームの一覧で、移行しない
+        let result = if key.starts_with(User::PERSIST_KEY_PREFIX) {
+            fs::read(&path).map_err(Into::into).and_then(|bytes| {
+                // `User(String)` として保存されていたので Steam ID の文字列として読める
+                let steam_id = bincode::deserialize::<String>(&bytes)
+                    .context("invalid shuttle-persist data")?;
+                migrate_record(key, User::new(steam_id), target, on_conflict)
+            })
+        } else if key.parse::<u64>().is_ok() {
+            // 以前の移行で書き込んだものがあれば消しておく
+            let migrated = format!("{}{key}", CommonGamesStore::PERSIST_KEY_PREFIX);
+            match target.remove(&migrated) {
+                Ok(()) => report.discarded.push(key.to_string()),
+                Err(e) => report.failed.push((key.to_string(), format!("{e:#}"))),
+            }
+            continue;
+        } else {
+            report.skipped.push(key.to_string());
+            continue;
+        };
+
+        let key = key.to_string();
+        match result {
+            Ok(Outcome::Migrated) => report.migrated.push(key),
+            Ok(Outcome::Unchanged) => report.unchanged.push(key),
+            Ok(Outcome::Conflict) => report.conflicts.push(key),
+            Err(e) => report.failed.push((key, format!("{e:#}"))),
+        }
+    }
+
+    Ok(report)
+}
+
+enum Outcome {
+    Migrated,
+    Unchanged,
+    Conflict,
+}
+
+fn migrate_record<T: Record + PartialEq>(
+    key: &str,
+    value: T,
+    target: &Storage,
+    on_conflict: OnConflict,
+) -> Result<Outcome> {
+    match target.load::<T>(key) {
+        Ok(existing) if existing == value => return Ok(Outcome::Unchanged),
+        Err(LoadError::Missing) => {}
+        _ if on_conflict == OnConflict::Skip => return Ok(Outcome::Conflict),
+        _ => {}
+    }
+
+    target.save(key, &value)?;
+    Ok(Outcome::Migrated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::LocalStorage;
+
+    struct Fixture {
+        source: tempfile::TempDir,
+        target_dir: tempfile::TempDir,
+        target: Storage,
+    }
+
+    impl Fixture {
+        fn new() -> Fixture {
+            let source = tempfile::tempdir().unwrap();
+            let target_dir = tempfile::tempdir().unwrap();
+            let target = LocalStorage::new(target_dir.path()).unwrap().into();
+            Fixture {
+                source,
+                target_dir,
+                target,
+            }
+        }
+
+        fn write(&self, key: &str, bytes: &[u8]) {
+            fs::write(self.source.path().join(format!("{key}.bin")), bytes).unwrap();
+        }
+
+        fn write_user(&self, discord_id: &str, steam_id: &str) {
+            let bytes = bincode::serialize(&steam_id.to_string()).unwrap();
+            self.write(&format!("{}{discord_id}", User::PERSIST_KEY_PREFIX), &bytes);
+        }
+
+        fn write_common_games(&self, discord_id: &str) {
+            self.write(discord_id, b"legacy common games");
+        }
+
+        fn migrate(&self, on_conflict: OnConflict) -> MigrationReport {
+            migrate(self.source.path(), &self.target, on_conflict).unwrap()
+        }
+
+        fn target_files(&self) -> Vec<(String, String)> {
+            let mut files = fs::read_dir(self.target_dir.path())
+                .unwrap()
+                .map(|entry| {
+                    let path = entry.unwrap().path();
+                    let name = path.file_name().unwrap().to_string_lossy().to_string();
+                    (name, fs::read_to_string(path).unwrap())
+                })
+                .collect::<Vec<_>>();
+            files.sort();
+            files
+        }
+    }
+
+    #[test]
+    fn converts_users_and_discards_common_games() {
+        let fixture = Fixture::new();
+        fixture.write_user("1", "76561198000000001");
+        fixture.write_common_games("2");
+        // 以前の移行で書き込んだ一覧は消す
+        fs::write(fixture.target_dir.path().join("common-games-2.json"), "{}").unwrap();
+
+        let report = fixture.migrate(OnConflict::Skip);
+        assert_eq!(report.migrated, ["discord-user-1"]);
+        assert_eq!(report.discarded, ["2"]);
+        assert!(report.failed.is_empty());
+
+        let user = User::load("1", &fixture.target).unwrap();
+        assert_eq!(user.steam_id(), "76561198000000001");
+        assert!(matches!(
+            fixture.target.load::<CommonGamesStore>("common-games-2"),
+            Err(LoadError::Missing)
+        ));
+    }
+
+    #[test]
+    fn rerun_changes_nothing() {
+        let fixture = Fixture::new();
+        fixture.write_user("1", "76561198000000001");
+        fixture.write_common_games("2");
+
+        fixture.migrate(OnConflict::Skip);
+        let before = fixture.target_files();
+        let report = fixture.migrate(OnConflict::Skip);
+
+        assert!(report.migrated.is_empty());
+        assert_eq!(report.unchanged, ["discord-user-1"]);
+        assert_eq!(report.discarded, ["2"]);
+        assert_eq!(fixture.target_files(), before);
+    }
+
+    #[test]
+    fn reports_conflicts_and_overwrites_only_when_asked() {
+        let fixture = Fixture::new();
+        fixture.write_user("1", "76561198000000001");
+        User::new("76561198000000002".to_string())
+            .save("1", &fixture.target)
+            .unwrap();
+
+        let report = fixture.migrate(OnConflict::Skip);
+        assert_eq!(report.conflicts, ["discord-user-1"]);
+        let user = User::load("1", &fixture.target).unwrap();
+        assert_eq!(user.steam_id(), "76561198000000002");
+
+        let report = fixture.migrate(OnConflict::Overwrite);
+        assert_eq!(report.migrated, ["discord-user-1"]);
+        let user = User::load("1", &fixture.target).unwrap();
+        assert_eq!(user.steam_id(), "76561198000000001");
+    }
+
+    #[test]
+    fn reports_skipped_and_failed_keys() {
+        let fixture = Fixture::new();
+        fixture.write("unknown", b"");
+        fixture.write("discord-user-1", b"");
+        fs::write(fixture.source.path().join("notes.txt"), "").unwrap();
+
+        let report = fixture.migrate(OnConflict::Skip);
+        assert_eq!(report.skipped, ["unknown"]);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].0, "discord-user-1");
+        assert!(fixture.target_files().is_empty());
+    }
+}
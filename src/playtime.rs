@@ -0,0 +1,200 @@
+//! 週ごとのプレイ時間の増え方を求めるための、プレイ時間の記録
+
+use std::{collections::HashMap, time::Duration};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serenity::model::Timestamp;
+
+use crate::{
+    common_games::AppId,
+    library_sync::StoredLibrary,
+    steam::Library,
+    storage::{LoadError, Record, Storage},
+    user::User,
+};
+
+/// 記録を残しておく期間
+///
+/// 1 週間前の記録を比べられるように少し長めに残す
+const RETENTION: Duration = Duration::from_secs(14 * 24 * 60 * 60);
+
+/// 増え方を比べる期間
+pub const WEEK: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// 前回の記録からこれだけ経っていないユーザーは記録しない
+///
+/// 再起動するたびに記録が増えないようにする。1 日ごとに記録するときに少し早く呼ばれても飛ばさないように、1 日より少し短くする
+const MIN_RECORD_INTERVAL: Duration = Duration::from_secs(23 * 60 * 60);
+
+/// ある時点のゲームごとの合計のプレイ時間 (分)
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Snapshot {
+    pub taken_at: Timestamp,
+    pub playtimes: HashMap<AppId, u64>,
+}
+
+impl Snapshot {
+    /// `taken_at` はライブラリを取得した時刻
+    fn new(library: &Library, taken_at: Timestamp) -> Snapshot {
+        Snapshot {
+            taken_at,
+            playtimes: library
+                .iter()
+                .filter(|(_, game)| game.playtime_forever > 0)
+                .map(|(appid, game)| (*appid, game.playtime_forever))
+                .collect(),
+        }
+    }
+
+    /// すべてのゲームの合計、または `appid` のゲームのプレイ時間
+    pub fn playtime(&self, appid: Option<AppId>) -> u64 {
+        match appid {
+            Some(appid) => self.playtimes.get(&appid).copied().unwrap_or_default(),
+            None => self.playtimes.values().sum(),
+        }
+    }
+}
+
+/// ユーザーごとのプレイ時間の記録
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+pub struct PlaytimeHistory {
+    /// 古い順
+    snapshots: Vec<Snapshot>,
+}
+
+impl PlaytimeHistory {
+    /// 保存するときのキーの接頭辞
+    pub const PERSIST_KEY_PREFIX: &'static str = "playtime-history-";
+
+    /// 記録がないときは空の記録にする
+    pub fn load(discord_id: &str, storage: &Storage) -> Result<PlaytimeHistory, LoadError> {
+        match storage.load(&Self::generate_persist_key(discord_id)) {
+            Err(LoadError::Missing) => Ok(PlaytimeHistory::default()),
+            result => result,
+        }
+    }
+
+    pub fn save(&self, discord_id: &str, storage: &Storage) -> Result<()> {
+        storage.save(&Self::generate_persist_key(discord_id), self)
+    }
+
+    fn generate_persist_key(discord_id: &str) -> String {
+        format!("{}{discord_id}", Self::PERSIST_KEY_PREFIX)
+    }
+
+    /// `ago` より前の記録のうち最も新しいもの
+    ///
+    /// 記録を始めてから `ago` 経っていないときは、増え方が短い期間のものになるので `None` にする
+    pub fn since(&self, ago: Duration) -> Option<&Snapshot> {
+        let threshold = Timestamp::now().unix_timestamp() - ago.as_secs() as i64;
+        self.snapshots
+            .iter()
+            .rev()
+            .find(|snapshot| snapshot.taken_at.unix_timestamp() <= threshold)
+    }
+
+    /// 最も新しい記録から `interval` 経っていない
+    fn is_recent(&self, interval: Duration) -> bool {
+        let threshold = Timestamp::now().unix_timestamp() - interval.as_secs() as i64;
+        self.snapshots
+            .last()
+            .is_some_and(|snapshot| snapshot.taken_at.unix_timestamp() > threshold)
+    }
+
+    /// 記録を加えて、古くなった記録を消す
+    fn push(&mut self, snapshot: Snapshot) {
+        let threshold = snapshot.taken_at.unix_timestamp() - RETENTION.as_secs() as i64;
+        self.snapshots
+            .retain(|snapshot| snapshot.taken_at.unix_timestamp() >= threshold);
+        self.snapshots.push(snapshot);
+    }
+
+    /// 登録しているすべてのユーザーの、保存しているライブラリのプレイ時間を記録する
+    ///
+    /// ライブラリは [`StoredLibrary`] が順番に取得しなおしているので、API は呼ばない。
+    /// 前回の記録から 1 日経っていないユーザーと、前回から取得しなおしていないユーザーは飛ばす
+    pub fn record_all(storage: &Storage) -> Result<usize> {
+        let mut recorded = 0;
+        for (discord_id, user) in User::load_all(storage)? {
+            let mut history = match Self::load(&discord_id, storage) {
+                Ok(history) => history,
+                // 壊れた記録は作り直す
+                Err(e) => {
+                    tracing::warn!("{e:?}");
+                    PlaytimeHistory::default()
+                }
+            };
+            if history.is_recent(MIN_RECORD_INTERVAL) {
+                continue;
+            }
+            let stored = match StoredLibrary::load(&discord_id, storage) {
+                // 登録しなおしたあとまだ取得していないライブラリは、ほかのアカウントのもの
+                Ok(stored) if stored.steam_id() == user.steam_id() => stored,
+                Ok(_) | Err(LoadError::Missing) => continue,
+                Err(e) => {
+                    tracing::warn!("{e:?}");
+                    continue;
+                }
+            };
+            let fetched_at = stored.fetched_at();
+            if history.snapshots.last().is_some_and(|snapshot| {
+                snapshot.taken_at.unix_timestamp() >= fetched_at.unix_timestamp()
+            }) {
+                continue;
+            }
+            history.push(Snapshot::new(&stored.library(false), fetched_at));
+            history.save(&discord_id, storage)?;
+            recorded += 1;
+        }
+        Ok(recorded)
+    }
+}
+
+impl Record for PlaytimeHistory {
+    const VERSION: u32 = 1;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: i64 = 24 * 60 * 60;
+
+    fn history(days_ago: &[i64]) -> PlaytimeHistory {
+        let now = Timestamp::now().unix_timestamp();
+        PlaytimeHistory {
+            snapshots: days_ago
+                .iter()
+                .map(|days| Snapshot {
+                    taken_at: Timestamp::from_unix_timestamp(now - days * DAY).unwrap(),
+                    playtimes: HashMap::from([(1, 100 - *days as u64)]),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn since_uses_newest_snapshot_a_week_old() {
+        let history = history(&[9, 8, 7, 3, 1]);
+        assert_eq!(history.since(WEEK).unwrap().playtime(Some(1)), 93);
+    }
+
+    #[test]
+    fn since_is_none_without_a_week_of_snapshots() {
+        assert!(history(&[6, 3, 1]).since(WEEK).is_none());
+        assert!(history(&[]).since(WEEK).is_none());
+    }
+
+    #[test]
+    fn recent_snapshot_is_not_recorded_again() {
+        let now = Timestamp::now().unix_timestamp();
+        let mut history = history(&[2]);
+        assert!(!history.is_recent(MIN_RECORD_INTERVAL));
+        history.snapshots.push(Snapshot {
+            taken_at: Timestamp::from_unix_timestamp(now - 60 * 60).unwrap(),
+            playtimes: HashMap::new(),
+        });
+        assert!(history.is_recent(MIN_RECORD_INTERVAL));
+    }
+}
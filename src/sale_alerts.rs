@@ -0,0 +1,335 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serenity::{
+    client::Context,
+    model::id::{ChannelId, GuildId},
+};
+
+use crate::{
+    common_games::{store_url, AppId},
+    group::{common_wishlist, load_libraries_and_wishlists, near_misses, registered_members},
+    hidden::HiddenGames,
+    steam::{Game, SteamApiClient},
+    storage::{LoadError, Record, Storage},
+    store::{AppDetails, StoreApiClient},
+};
+
+/// あと少しで揃うゲームとして見張る、持っていない人数の上限
+const NEAR_MISS_MAX_MISSING: usize = 1;
+
+/// 見張るゲームの数の上限
+///
+/// ストアに問い合わせる数を抑えるため
+const MAX_WATCHED: usize = 100;
+
+/// 1 回に投稿するセールの数の上限
+const MAX_ANNOUNCED: usize = 10;
+
+/// 設定を読んでから保存するまでの間に、ほかの処理が書き換えないようにする
+///
+/// 確認には時間がかかるので、その間に解除されたり通知先が変わったりすることがある
+static LOCK: Mutex<()> = Mutex::new(());
+
+/// サーバーのセールの通知の設定
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+pub struct SaleAlerts {
+    /// 通知を投稿するチャンネル
+    pub channel_id: ChannelId,
+    /// 前回調べたときのゲームごとの割引率
+    ///
+    /// 割引率が変わったときだけ通知して、同じセールを何度も通知しないようにする
+    #[serde(default)]
+    last_discounts: HashMap<AppId, u64>,
+}
+
+impl SaleAlerts {
+    /// 保存するときのキーの接頭辞
+    pub const PERSIST_KEY_PREFIX: &'static str = "sale-alerts-";
+
+    pub fn new(channel_id: ChannelId) -> SaleAlerts {
+        SaleAlerts {
+            channel_id,
+            last_discounts: HashMap::new(),
+        }
+    }
+
+    pub fn load(guild_id: GuildId, storage: &Storage) -> Result<SaleAlerts, LoadError> {
+        storage.load(&Self::generate_persist_key(guild_id))
+    }
+
+    fn save(&self, guild_id: GuildId, storage: &Storage) -> Result<()> {
+        storage.save(&Self::generate_persist_key(guild_id), self)
+    }
+
+    /// 通知を `channel_id` に投稿するようにする
+    ///
+    /// 通知先を変えるときも、同じセールを通知しなおさないように前回の割引率は引き継ぐ
+    pub fn subscribe(guild_id: GuildId, channel_id: ChannelId, storage: &Storage) -> Result<()> {
+        let _lock = LOCK.lock().unwrap();
+        let mut alerts = match Self::load(guild_id, storage) {
+            Ok(alerts) => alerts,
+            Err(LoadError::Missing) => SaleAlerts::new(channel_id),
+            Err(e @ LoadError::Corrupt(_)) => {
+                tracing::warn!("{e:?}");
+                SaleAlerts::new(channel_id)
+            }
+        };
+        alerts.channel_id = channel_id;
+        alerts.save(guild_id, storage)
+    }
+
+    pub fn remove(guild_id: GuildId, storage: &Storage) -> Result<()> {
+        let _lock = LOCK.lock().unwrap();
+        storage.remove(&Self::generate_persist_key(guild_id))
+    }
+
+    /// 通知の設定を読んで書き換え、保存する
+    ///
+    /// 確認している間に設定が変わっていても上書きしないように、読んでから保存するまでを順番に行う。
+    /// 解除されていたときは `None` を返す
+    fn update(
+        guild_id: GuildId,
+        storage: &Storage,
+        f: impl FnOnce(&mut SaleAlerts),
+    ) -> Result<Option<SaleAlerts>> {
+        let _lock = LOCK.lock().unwrap();
+        let mut alerts = match Self::load(guild_id, storage) {
+            Ok(alerts) => alerts,
+            Err(LoadError::Missing) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        f(&mut alerts);
+        alerts.save(guild_id, storage)?;
+        Ok(Some(alerts))
+    }
+
+    fn generate_persist_key(guild_id: GuildId) -> String {
+        format!("{}{guild_id}", Self::PERSIST_KEY_PREFIX)
+    }
+
+    /// 通知を設定しているサーバー
+    pub fn guild_ids(storage: &Storage) -> Result<Vec<GuildId>> {
+        Ok(storage
+            .keys(Self::PERSIST_KEY_PREFIX)?
+            .into_iter()
+            .filter_map(|key| key[Self::PERSIST_KEY_PREFIX.len()..].parse().ok())
+            .map(GuildId)
+            .collect())
+    }
+
+    /// 通知を設定しているすべてのサーバーについて、セールが始まったゲームを投稿する
+    pub async fn check_all(
+        ctx: &Context,
+        steam: &SteamApiClient,
+        steam_store: &StoreApiClient,
+        storage: &Storage,
+    ) -> Result<()> {
+        for guild_id in Self::guild_ids(storage)? {
+            if let Err(e) = Self::check(ctx, guild_id, steam, steam_store, storage).await {
+                tracing::warn!("{e:?}");
+            }
+        }
+        Ok(())
+    }
+
+    /// サーバーで登録しているメンバーの共通のウィッシュリストと、あと少しで揃うゲームの価格を調べる
+    ///
+    /// 価格は日本のストアのものを使う
+    async fn check(
+        ctx: &Context,
+        guild_id: GuildId,
+        steam: &SteamApiClient,
+        steam_store: &StoreApiClient,
+        storage: &Storage,
+    ) -> Result<()> {
+        let alerts = match Self::load(guild_id, storage) {
+            Ok(alerts) => alerts,
+            // 確認している間に解除された
+            Err(LoadError::Missing) => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        let Some(guild) = guild_id.to_guild_cached(ctx) else {
+            return Ok(());
+        };
+
+        let members = registered_members(ctx, &guild, storage).await?;
+        let (libraries, wishlists) = load_libraries_and_wishlists(members, steam, storage).await;
+        if libraries.len() < 2 {
+            return Ok(());
+        }
+        let hidden = HiddenGames::collect(
+            guild_id,
+            libraries.iter().map(|(member, _)| member.user_id),
+            storage,
+        );
+
+        // 全員が欲しがっているものを先に、残りをあと少しで揃うものから関係の深い順に埋める
+        let mut wishlist = common_wishlist(&libraries, &wishlists, &hidden);
+        wishlist.sort();
+        wishlist.truncate(MAX_WATCHED);
+        let near_misses = near_misses(&libraries, &hidden, NEAR_MISS_MAX_MISSING)
+            .into_iter()
+            .filter(|game| !wishlist.contains(&game.appid))
+            .collect();
+        let near_misses = steam_store
+            .filter_games(near_misses, MAX_WATCHED - wishlist.len())
+            .await;
+        let watched = wishlist
+            .into_iter()
+            .map(|appid| (appid, "全員が欲しがっています"))
+            .chain(
+                near_misses
+                    .into_iter()
+                    .map(|Game { appid, .. }| (appid, "あと1人で全員が持っています")),
+            )
+            .collect::<Vec<_>>();
+
+        let appids = watched.iter().map(|(appid, _)| *appid).collect::<Vec<_>>();
+        let details = steam_store.get_app_details_many(appids).await;
+        let (mut sales, last_discounts) = find_sales(&watched, &details, &alerts.last_discounts);
+
+        if sales.is_empty() {
+            Self::update(guild_id, storage, |alerts| {
+                alerts.last_discounts = last_discounts;
+            })?;
+            return Ok(());
+        }
+        // 割引率の大きいものから投稿する
+        sales.sort_by_key(|(details, _)| {
+            std::cmp::Reverse(details.price.as_ref().map_or(0, |p| p.discount_percent))
+        });
+        let mut content = "みんなが欲しがっているゲームがセール中です。".to_string();
+        if sales.len() > MAX_ANNOUNCED {
+            content.push_str(&format!("(ほか{}件)", sales.len() - MAX_ANNOUNCED));
+            sales.truncate(MAX_ANNOUNCED);
+        }
+
+        // 確認している間に解除されたり、通知先が変わったりしていることがあるので読みなおす
+        let channel_id = match Self::load(guild_id, storage) {
+            Ok(alerts) => alerts.channel_id,
+            Err(LoadError::Missing) => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        channel_id
+            .send_message(ctx, |msg| {
+                msg.content(content);
+                for (details, reason) in &sales {
+                    msg.add_embed(|embed| {
+                        embed
+                            .title(&details.name)
+                            .url(store_url(details.appid))
+                            .description(reason)
+                            .field("価格", details.price_text(), true);
+                        if let Some(image) = &details.capsule_image {
+                            embed.thumbnail(image);
+                        }
+                        embed
+                    });
+                }
+                msg
+            })
+            .await?;
+        // 投稿できなかったときは、次に確認したときにもう一度投稿する
+        Self::update(guild_id, storage, |alerts| {
+            alerts.last_discounts = last_discounts;
+        })?;
+        Ok(())
+    }
+}
+
+/// 見張っているゲームのうち、前回から割引率が変わってセールになったものと、次に比べるための割引率
+///
+/// 詳細を取得できなかったゲームは前回の割引率を引き継ぐ
+fn find_sales<'a>(
+    watched: &[(AppId, &'a str)],
+    details: &HashMap<AppId, AppDetails>,
+    previous: &HashMap<AppId, u64>,
+) -> (Vec<(AppDetails, &'a str)>, HashMap<AppId, u64>) {
+    let mut sales = Vec::new();
+    let mut last_discounts = HashMap::new();
+    for (appid, reason) in watched {
+        let Some(details) = details.get(appid) else {
+            if let Some(discount) = previous.get(appid) {
+                last_discounts.insert(*appid, *discount);
+            }
+            continue;
+        };
+        let discount = details
+            .price
+            .as_ref()
+            .map_or(0, |price| price.discount_percent);
+        if discount > 0 && previous.get(appid) != Some(&discount) {
+            sales.push((details.clone(), *reason));
+        }
+        last_discounts.insert(*appid, discount);
+    }
+    (sales, last_discounts)
+}
+
+impl Record for SaleAlerts {
+    const VERSION: u32 = 1;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::Price;
+
+    fn details(appid: AppId, discount_percent: Option<u64>) -> AppDetails {
+        AppDetails {
+            appid,
+            name: format!("Game {appid}"),
+            kind: "game".to_string(),
+            header_image: None,
+            capsule_image: None,
+            is_free: false,
+            price: discount_percent.map(|discount_percent| Price {
+                currency: "JPY".to_string(),
+                initial: 100000,
+                final_: 100000 * (100 - discount_percent) / 100,
+                discount_percent,
+                initial_formatted: String::new(),
+                final_formatted: String::new(),
+            }),
+            categories: Vec::new(),
+            genres: Vec::new(),
+            review: None,
+        }
+    }
+
+    #[test]
+    fn announces_only_new_or_changed_discounts() {
+        let watched = [(1, "a"), (2, "b"), (3, "c"), (4, "d")];
+        let details = HashMap::from([
+            (1, details(1, Some(50))),
+            (2, details(2, Some(50))),
+            (3, details(3, Some(75))),
+            (4, details(4, Some(0))),
+        ]);
+        let previous = HashMap::from([(2, 50), (3, 50), (4, 30)]);
+
+        let (sales, last_discounts) = find_sales(&watched, &details, &previous);
+        let announced = sales
+            .iter()
+            .map(|(details, reason)| (details.appid, *reason))
+            .collect::<Vec<_>>();
+        assert_eq!(announced, vec![(1, "a"), (3, "c")]);
+        assert_eq!(
+            last_discounts,
+            HashMap::from([(1, 50), (2, 50), (3, 75), (4, 0)])
+        );
+    }
+
+    #[test]
+    fn keeps_previous_discount_when_details_are_missing() {
+        let watched = [(1, "a"), (2, "b")];
+        let details = HashMap::from([(2, details(2, None))]);
+        let previous = HashMap::from([(1, 40)]);
+
+        let (sales, last_discounts) = find_sales(&watched, &details, &previous);
+        assert!(sales.is_empty());
+        assert_eq!(last_discounts, HashMap::from([(1, 40), (2, 0)]));
+    }
+}
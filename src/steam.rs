@@ -1,29 +1,175 @@
-use std::collections::HashSet;
+use std::{collections::HashMap, time::Duration};
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use serde::{Deserialize, Serialize};
 
+use crate::{cache::TtlCache, common_games::AppId};
+
+/// 取得した所有ゲームの一覧をキャッシュしておく期間
+const LIBRARY_CACHE_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// 取得した実績の一覧をキャッシュしておく期間
+///
+/// ゲームの実績はめったに変わらないので長めにする
+const SCHEMA_CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
 /// [GetOwnedGames](https://developer.valvesoftware.com/wiki/Steam_Web_API#GetOwnedGames_.28v0001.29) response.
 ///
 /// - クエリに `include_appinfo=true`. を含む必要がある
-/// - ほかのフィールドを有効にするとユーザーごとに異なってきてしまうため除外している
+/// - プレイ時間などユーザーごとに異なるフィールドは [`OwnedGame`] に分けている
 #[derive(Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Debug)]
 pub struct Game {
     pub appid: u64,
     pub name: String,
 }
 
+/// アプリの種類
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AppType {
+    Game,
+    Tool,
+    Soundtrack,
+    DedicatedServer,
+    Demo,
+    Dlc,
+}
+
+impl AppType {
+    /// ストアの appdetails の `type` から種類を決める
+    pub fn from_store_type(kind: &str) -> AppType {
+        match kind {
+            "game" => AppType::Game,
+            "demo" => AppType::Demo,
+            "music" => AppType::Soundtrack,
+            "dlc" => AppType::Dlc,
+            // `application` や `video` などはツールとして扱う
+            _ => AppType::Tool,
+        }
+    }
+}
+
+impl Game {
+    /// 名前から推測したアプリの種類
+    ///
+    /// GetOwnedGames は種類を返さないので、ストアの情報を取得できなかったときに
+    /// SDK やサーバーなどの名前につく語で判断する
+    pub fn guess_app_type(&self) -> AppType {
+        let name = self.name.to_lowercase();
+        let words = name
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty())
+            .collect::<Vec<_>>();
+        let has_phrase = |phrase: &[&str]| words.windows(phrase.len()).any(|w| w == phrase);
+
+        if has_phrase(&["dedicated", "server"]) || has_phrase(&["test", "server"]) {
+            AppType::DedicatedServer
+        } else if has_phrase(&["soundtrack"]) || words.last() == Some(&"ost") {
+            AppType::Soundtrack
+        } else if words.last() == Some(&"demo")
+            || has_phrase(&["playable", "teaser"])
+            || name.contains("体験版")
+        {
+            AppType::Demo
+        } else if ["sdk", "benchmark", "editor"]
+            .iter()
+            .any(|w| words.contains(w))
+            || has_phrase(&["mod", "tools"])
+            || has_phrase(&["authoring", "tools"])
+        {
+            AppType::Tool
+        } else {
+            AppType::Game
+        }
+    }
+}
+
+/// ユーザーが所有しているゲームとそのプレイ時間
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+pub struct OwnedGame {
+    #[serde(flatten)]
+    pub game: Game,
+    /// 合計のプレイ時間 (分)
+    #[serde(default)]
+    pub playtime_forever: u64,
+    /// 直近 2 週間のプレイ時間 (分)
+    #[serde(default)]
+    pub playtime_2weeks: u64,
+    /// 自分は持っていないが、Steam ファミリーのほかのメンバーから借りられる
+    #[serde(default)]
+    pub family_shared: bool,
+}
+
+/// [GetPlayerSummaries](https://developer.valvesoftware.com/wiki/Steam_Web_API#GetPlayerSummaries_.28v0002.29) のプレイヤー
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PlayerSummary {
+    pub steamid: String,
+    pub personaname: String,
+    /// プロフィールに設定された国 (公開しているときだけ)
+    pub loccountrycode: Option<String>,
+    /// 遊んでいる最中のゲームの App ID (文字列)
+    pub gameid: Option<String>,
+    /// 遊んでいる最中のゲームの名前
+    pub gameextrainfo: Option<String>,
+    /// 参加できるロビーにいるときのロビーの ID
+    pub lobbysteamid: Option<String>,
+}
+
+/// [GetWishlist](https://partner.steamgames.com/doc/webapi/IWishlistService#GetWishlist) の項目
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WishlistItem {
+    pub appid: AppId,
+    /// 小さいほど優先度が高い
+    #[serde(default)]
+    pub priority: u32,
+    #[serde(default)]
+    pub date_added: i64,
+}
+
+/// [GetSchemaForGame](https://partner.steamgames.com/doc/webapi/ISteamUserStats#GetSchemaForGame) の実績
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AchievementSchema {
+    /// API で使う名前
+    pub name: String,
+    #[serde(rename = "displayName")]
+    pub display_name: String,
+    /// 隠し実績では空のことがある
+    #[serde(default)]
+    pub description: String,
+}
+
+/// [GetPlayerAchievements](https://developer.valvesoftware.com/wiki/Steam_Web_API#GetPlayerAchievements_.28v0001.29) の実績
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PlayerAchievement {
+    pub apiname: String,
+    /// 解除していれば 1
+    pub achieved: u8,
+}
+
+/// ユーザーが所有しているゲームの一覧
+pub type Library = HashMap<AppId, OwnedGame>;
+
 /// Steam Web API client.
 ///
 /// https://steamcommunity.com/dev
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 pub struct SteamApiClient {
     api_key: String,
+    /// Steam ID と無料のゲームを含めるかどうかごとの所有ゲームの一覧
+    library_cache: TtlCache<(String, bool), Library>,
+    /// Steam ID ごとのウィッシュリスト
+    wishlist_cache: TtlCache<String, Vec<WishlistItem>>,
+    /// ゲームごとの実績の一覧
+    schema_cache: TtlCache<AppId, Vec<AchievementSchema>>,
 }
 
 impl SteamApiClient {
     pub fn new(api_key: String) -> SteamApiClient {
-        SteamApiClient { api_key }
+        SteamApiClient {
+            api_key,
+            library_cache: TtlCache::new(LIBRARY_CACHE_TTL),
+            wishlist_cache: TtlCache::new(LIBRARY_CACHE_TTL),
+            schema_cache: TtlCache::new(SCHEMA_CACHE_TTL),
+        }
     }
 
     pub async fn get(&self, path: &str, query: &[(&str, &str)]) -> Result<reqwest::Response> {
@@ -36,15 +182,257 @@ impl SteamApiClient {
         Ok(resp)
     }
 
+    /// ウィッシュリストに入っているゲーム
+    ///
+    /// [GetWishlist](https://partner.steamgames.com/doc/webapi/IWishlistService#GetWishlist)
+    ///
+    /// 非公開のウィッシュリストは空になる。取得した一覧はしばらくキャッシュする
+    pub async fn get_wishlist(&self, steam_id: &str) -> Result<Vec<WishlistItem>> {
+        if let Some(items) = self.wishlist_cache.get(&steam_id.to_string()) {
+            return Ok(items);
+        }
+
+        #[derive(Deserialize, Debug)]
+        struct Wishlist {
+            #[serde(default)]
+            items: Vec<WishlistItem>,
+        }
+
+        #[derive(Deserialize, Debug)]
+        struct WishlistResponse {
+            response: Wishlist,
+        }
+
+        let WishlistResponse {
+            response: Wishlist { items },
+        } = self
+            .get("/IWishlistService/GetWishlist/v1", &[("steamid", steam_id)])
+            .await
+            .context("request failed")?
+            .json()
+            .await
+            .context("invalid json")?;
+        self.wishlist_cache
+            .insert(steam_id.to_string(), items.clone());
+        Ok(items)
+    }
+
+    /// プロフィールの概要
+    ///
+    /// [GetPlayerSummaries](https://developer.valvesoftware.com/wiki/Steam_Web_API#GetPlayerSummaries_.28v0002.29)
+    ///
+    /// 一度に 100 人まで。見つからなかったユーザーは含まれない
+    pub async fn get_player_summaries(&self, steam_ids: &[&str]) -> Result<Vec<PlayerSummary>> {
+        #[derive(Deserialize, Debug)]
+        struct Players {
+            players: Vec<PlayerSummary>,
+        }
+
+        #[derive(Deserialize, Debug)]
+        struct PlayerSummariesResponse {
+            response: Players,
+        }
+
+        let steam_ids = steam_ids.join(",");
+        let PlayerSummariesResponse {
+            response: Players { players },
+        } = self
+            .get(
+                "/ISteamUser/GetPlayerSummaries/v0002",
+                &[("steamids", steam_ids.as_str())],
+            )
+            .await
+            .context("request failed")?
+            .json()
+            .await
+            .context("invalid json")?;
+        Ok(players)
+    }
+
+    /// Steam のすべてのアプリの App ID と名前
+    ///
+    /// [GetAppList](https://developer.valvesoftware.com/wiki/Steam_Web_API#GetAppList_.28v0002.29)
+    pub async fn get_app_list(&self) -> Result<Vec<Game>> {
+        #[derive(Deserialize, Debug)]
+        struct AppList {
+            apps: Vec<Game>,
+        }
+
+        #[derive(Deserialize, Debug)]
+        struct AppListResponse {
+            applist: AppList,
+        }
+
+        let AppListResponse {
+            applist: AppList { apps },
+        } = self
+            .get("/ISteamApps/GetAppList/v2", &[])
+            .await
+            .context("request failed")?
+            .json()
+            .await
+            .context("invalid json")?;
+        Ok(apps)
+    }
+
+    /// ゲームの実績の一覧
+    ///
+    /// [GetSchemaForGame](https://partner.steamgames.com/doc/webapi/ISteamUserStats#GetSchemaForGame)
+    ///
+    /// 名前は日本語で取得する。実績のないゲームは空になる。取得した一覧はしばらくキャッシュする
+    pub async fn get_schema_for_game(&self, appid: AppId) -> Result<Vec<AchievementSchema>> {
+        if let Some(achievements) = self.schema_cache.get(&appid) {
+            return Ok(achievements);
+        }
+
+        #[derive(Deserialize, Debug, Default)]
+        struct AvailableGameStats {
+            #[serde(default)]
+            achievements: Vec<AchievementSchema>,
+        }
+
+        #[derive(Deserialize, Debug)]
+        struct Schema {
+            #[serde(default, rename = "availableGameStats")]
+            available_game_stats: AvailableGameStats,
+        }
+
+        #[derive(Deserialize, Debug)]
+        struct SchemaResponse {
+            game: Schema,
+        }
+
+        let appid_text = appid.to_string();
+        let SchemaResponse {
+            game:
+                Schema {
+                    available_game_stats: AvailableGameStats { achievements },
+                },
+        } = self
+            .get(
+                "/ISteamUserStats/GetSchemaForGame/v2",
+                &[("appid", appid_text.as_str()), ("l", "japanese")],
+            )
+            .await
+            .context("request failed")?
+            .json()
+            .await
+            .context("invalid json")?;
+        self.schema_cache.insert(appid, achievements.clone());
+        Ok(achievements)
+    }
+
+    /// プレイヤーの実績の解除状況
+    ///
+    /// [GetPlayerAchievements](https://developer.valvesoftware.com/wiki/Steam_Web_API#GetPlayerAchievements_.28v0001.29)
+    ///
+    /// プロフィールが非公開のときや持っていないときはエラーになる
+    pub async fn get_player_achievements(
+        &self,
+        steam_id: &str,
+        appid: AppId,
+    ) -> Result<Vec<PlayerAchievement>> {
+        #[derive(Deserialize, Debug)]
+        struct PlayerStats {
+            success: bool,
+            #[serde(default)]
+            achievements: Vec<PlayerAchievement>,
+            #[serde(default)]
+            error: Option<String>,
+        }
+
+        #[derive(Deserialize, Debug)]
+        struct PlayerStatsResponse {
+            playerstats: PlayerStats,
+        }
+
+        let appid_text = appid.to_string();
+        let PlayerStatsResponse { playerstats } = self
+            .get(
+                "/ISteamUserStats/GetPlayerAchievements/v0001",
+                &[("steamid", steam_id), ("appid", appid_text.as_str())],
+            )
+            .await
+            .context("request failed")?
+            .json()
+            .await
+            .context("invalid json")?;
+        if !playerstats.success {
+            bail!(
+                "failed to get achievements: {}",
+                playerstats.error.unwrap_or_default()
+            );
+        }
+        Ok(playerstats.achievements)
+    }
+
+    /// 直近 2 週間に遊んだゲーム
+    ///
+    /// [GetRecentlyPlayedGames](https://developer.valvesoftware.com/wiki/Steam_Web_API#GetRecentlyPlayedGames_.28v0001.29)
+    ///
+    /// 遊んでいないときや非公開のときは空になる。遊んでいる最中に変わるのでキャッシュしない
+    pub async fn get_recently_played_games(&self, steam_id: &str) -> Result<Vec<OwnedGame>> {
+        #[derive(Deserialize, Debug)]
+        struct RecentGames {
+            #[serde(default)]
+            games: Vec<OwnedGame>,
+        }
+
+        #[derive(Deserialize, Debug)]
+        struct RecentGamesResponse {
+            response: RecentGames,
+        }
+
+        let RecentGamesResponse {
+            response: RecentGames { games },
+        } = self
+            .get(
+                "/IPlayerService/GetRecentlyPlayedGames/v0001",
+                &[("steamid", steam_id)],
+            )
+            .await
+            .context("request failed")?
+            .json()
+            .await
+            .context("invalid json")?;
+        Ok(games)
+    }
+
     /// Returns a list of games a player owns along with some playtime information, if the profile is publicly visible.
     /// Private, friends-only, and other privacy settings are not supported unless you are asking for your own personal details (ie the WebAPI key you are using is linked to the steamid you are requesting).
     ///
     /// [GetOwnedGames](https://developer.valvesoftware.com/wiki/Steam_Web_API#GetOwnedGames_.28v0001.29)
-    pub async fn get_owned_games(&self, steam_id: &str) -> Result<HashSet<Game>> {
+    ///
+    /// 取得した一覧はしばらくキャッシュする
+    pub async fn get_owned_games(&self, steam_id: &str) -> Result<Library> {
+        self.get_owned_games_with(steam_id, false).await
+    }
+
+    /// `include_played_free_games` が `true` なら、一度でも遊んだ無料のゲームも含める
+    pub async fn get_owned_games_with(
+        &self,
+        steam_id: &str,
+        include_played_free_games: bool,
+    ) -> Result<Library> {
+        let key = (steam_id.to_string(), include_played_free_games);
+        if let Some(games) = self.library_cache.get(&key) {
+            return Ok(games);
+        }
+        self.fetch_owned_games(steam_id, include_played_free_games)
+            .await
+    }
+
+    /// キャッシュを使わずにライブラリを取得する
+    ///
+    /// 取得した一覧でキャッシュも新しくする
+    pub async fn fetch_owned_games(
+        &self,
+        steam_id: &str,
+        include_played_free_games: bool,
+    ) -> Result<Library> {
         #[derive(Deserialize, Debug)]
         pub struct OwnedGames {
-            pub game_count: usize,
-            pub games: HashSet<Game>,
+            pub games: Vec<OwnedGame>,
         }
 
         #[derive(Deserialize, Debug)]
@@ -53,21 +441,68 @@ impl SteamApiClient {
         }
 
         let OwnedGamesResponse {
-            response:
-                OwnedGames {
-                    game_count: _,
-                    games,
-                },
+            response: OwnedGames { games },
         } = self
             .get(
                 "/IPlayerService/GetOwnedGames/v0001",
-                &[("steamid", steam_id), ("include_appinfo", "true")],
+                &[
+                    ("steamid", steam_id),
+                    ("include_appinfo", "true"),
+                    (
+                        "include_played_free_games",
+                        if include_played_free_games {
+                            "true"
+                        } else {
+                            "false"
+                        },
+                    ),
+                ],
             )
             .await
             .context("request failed")?
             .json()
             .await
             .context("invalid json")?;
+        let games = games
+            .into_iter()
+            .map(|game| (game.game.appid, game))
+            .collect::<Library>();
+        self.library_cache.insert(
+            (steam_id.to_string(), include_played_free_games),
+            games.clone(),
+        );
         Ok(games)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn store_type_decides_app_type() {
+        assert_eq!(AppType::from_store_type("game"), AppType::Game);
+        assert_eq!(AppType::from_store_type("dlc"), AppType::Dlc);
+        assert_eq!(AppType::from_store_type("music"), AppType::Soundtrack);
+        assert_eq!(AppType::from_store_type("demo"), AppType::Demo);
+        assert_eq!(AppType::from_store_type("application"), AppType::Tool);
+    }
+
+    #[test]
+    fn guesses_app_type_from_name() {
+        let guess = |name: &str| {
+            Game {
+                appid: 0,
+                name: name.to_string(),
+            }
+            .guess_app_type()
+        };
+        assert_eq!(guess("Portal 2"), AppType::Game);
+        assert_eq!(guess("Portal 2 Soundtrack"), AppType::Soundtrack);
+        assert_eq!(
+            guess("Garry's Mod Dedicated Server"),
+            AppType::DedicatedServer
+        );
+        assert_eq!(guess("Source SDK"), AppType::Tool);
+    }
+}
@@ -0,0 +1,272 @@
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+#[cfg(feature = "shuttle")]
+use shuttle_persist::{PersistError, PersistInstance};
+
+/// 永続化するデータ
+///
+/// 保存するときはスキーマのバージョンと一緒に JSON にして保存する。
+/// 型を変更するときは [`Record::VERSION`] を上げて、古いバージョンからの変換を [`Record::upgrade`] に書く。
+/// `#[serde(default)]` のフィールドを加えるだけなら古いデータもそのまま読めるので、バージョンは上げない。
+pub trait Record: Serialize + DeserializeOwned {
+    /// 保存するときに一緒に記録するスキーマのバージョン
+    const VERSION: u32;
+
+    /// 古いバージョンで保存されたデータを現在の型に変換する
+    ///
+    /// バージョン 0 はバージョンをつけずに保存されていたデータで、保存されていた中身をそのまま文字列で渡す
+    fn upgrade(version: u32, _data: Value) -> Result<Self> {
+        bail!("unsupported schema version {version}")
+    }
+}
+
+/// 保存されたデータを読むときのエラー
+#[derive(Debug)]
+pub enum LoadError {
+    /// 保存されていない
+    Missing,
+    /// 保存されているが読むことができない
+    Corrupt(anyhow::Error),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Missing => write!(f, "record not found"),
+            LoadError::Corrupt(e) => write!(f, "corrupt record: {e:#}"),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+/// 永続化先
+///
+/// - shuttle で動かすときは [`PersistInstance`] を使う
+/// - 単体で動かすときはローカルのディレクトリに JSON で保存する
+#[derive(Clone)]
+pub enum Storage {
+    #[cfg(feature = "shuttle")]
+    Shuttle {
+        persist: PersistInstance,
+        /// [`PersistInstance`] が保存しているディレクトリ
+        dir: PathBuf,
+    },
+    Local(LocalStorage),
+}
+
+impl Storage {
+    /// shuttle-persist に保存する
+    ///
+    /// [`PersistInstance`] は一覧と削除を提供していないので、
+    /// 保存先の `shuttle_persist/{サービス名}` を直接扱うためにサービス名も受け取る
+    #[cfg(feature = "shuttle")]
+    pub fn shuttle(persist: PersistInstance, service_name: &str) -> Storage {
+        Storage::Shuttle {
+            persist,
+            dir: ["shuttle_persist", service_name].iter().collect(),
+        }
+    }
+
+    pub fn save<T: Record>(&self, key: &str, value: &T) -> Result<()> {
+        let text = serde_json::to_string(&Versioned {
+            version: T::VERSION,
+            data: value,
+        })?;
+        match self {
+            #[cfg(feature = "shuttle")]
+            Storage::Shuttle { persist, .. } => persist.save(key, text)?,
+            Storage::Local(local) => local.write(key, &text)?,
+        }
+        Ok(())
+    }
+
+    pub fn load<T: Record>(&self, key: &str) -> Result<T, LoadError> {
+        let text = match self {
+            #[cfg(feature = "shuttle")]
+            Storage::Shuttle { persist, .. } => match persist.load::<String>(key) {
+                Ok(text) => text,
+                Err(PersistError::Open(e)) if e.kind() == io::ErrorKind::NotFound => {
+                    return Err(LoadError::Missing)
+                }
+                Err(e) => return Err(LoadError::Corrupt(e.into())),
+            },
+            Storage::Local(local) => local.read(key)?,
+        };
+        decode(&text).map_err(|e| LoadError::Corrupt(e.context(format!("key: {key}"))))
+    }
+
+    pub fn remove(&self, key: &str) -> Result<()> {
+        let path = self.dir().join(format!("{key}.{}", self.extension()));
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// `prefix` から始まる保存済みのキーをすべて返す
+    pub fn keys(&self, prefix: &str) -> Result<Vec<String>> {
+        let entries = match fs::read_dir(self.dir()) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut keys = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(self.extension()) {
+                continue;
+            }
+            if let Some(key) = path.file_stem().and_then(|stem| stem.to_str()) {
+                if key.starts_with(prefix) {
+                    keys.push(key.to_string());
+                }
+            }
+        }
+        keys.sort();
+        Ok(keys)
+    }
+
+    /// 保存先のディレクトリ
+    fn dir(&self) -> &Path {
+        match self {
+            #[cfg(feature = "shuttle")]
+            Storage::Shuttle { dir, .. } => dir,
+            Storage::Local(local) => &local.dir,
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            #[cfg(feature = "shuttle")]
+            Storage::Shuttle { .. } => "bin",
+            Storage::Local(_) => "json",
+        }
+    }
+}
+
+impl From<LocalStorage> for Storage {
+    fn from(local: LocalStorage) -> Storage {
+        Storage::Local(local)
+    }
+}
+
+/// スキーマのバージョンをつけて保存するための入れ物
+#[derive(Serialize, Deserialize, Debug)]
+struct Versioned<T> {
+    version: u32,
+    data: T,
+}
+
+/// 保存されていた文字列を現在の型にする
+///
+/// バージョンがついていないものは、バージョン 0 として中身をそのまま [`Record::upgrade`] に渡す。
+/// バージョンをつけずに保存していたのは JSON のオブジェクトではなかったので、
+/// オブジェクトに見えるのに読めないものは壊れているものとして扱う
+fn decode<T: Record>(text: &str) -> Result<T> {
+    if !text.trim_start().starts_with('{') {
+        return T::upgrade(0, Value::String(text.to_string()));
+    }
+    let Versioned { version, data } = serde_json::from_str::<Versioned<Value>>(text)?;
+    if version == T::VERSION {
+        return Ok(serde_json::from_value(data)?);
+    }
+    if version > T::VERSION {
+        return Err(anyhow!(
+            "schema version {version} is newer than {}",
+            T::VERSION
+        ));
+    }
+    T::upgrade(version, data)
+}
+
+/// ローカルのディレクトリに `{key}.json` として保存する
+#[derive(Clone, Debug)]
+pub struct LocalStorage {
+    dir: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(dir: impl Into<PathBuf>) -> Result<LocalStorage> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("failed to create storage directory {}", dir.display()))?;
+        Ok(LocalStorage { dir })
+    }
+
+    fn write(&self, key: &str, text: &str) -> Result<()> {
+        fs::write(self.path(key), text)?;
+        Ok(())
+    }
+
+    fn read(&self, key: &str) -> Result<String, LoadError> {
+        fs::read_to_string(self.path(key)).map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => LoadError::Missing,
+            _ => LoadError::Corrupt(e.into()),
+        })
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{key}.json"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Legacy(String);
+
+    impl Record for Legacy {
+        const VERSION: u32 = 1;
+
+        fn upgrade(version: u32, data: Value) -> Result<Self> {
+            match (version, data) {
+                (0, Value::String(text)) => Ok(Legacy(text)),
+                (version, _) => bail!("unsupported schema version {version}"),
+            }
+        }
+    }
+
+    #[test]
+    fn decodes_current_version() {
+        let decoded = decode::<Legacy>(r#"{"version":1,"data":"a"}"#).unwrap();
+        assert_eq!(decoded, Legacy("a".to_string()));
+    }
+
+    #[test]
+    fn upgrades_unversioned_text() {
+        let decoded = decode::<Legacy>("76561198000000001").unwrap();
+        assert_eq!(decoded, Legacy("76561198000000001".to_string()));
+    }
+
+    #[test]
+    fn reports_broken_envelope_as_parse_error() {
+        let e = decode::<Legacy>(r#"{"version":1,"data":"#).unwrap_err();
+        assert!(e.downcast_ref::<serde_json::Error>().is_some(), "{e:#}");
+        let e = decode::<Legacy>(r#"{"version":1,"data":1}"#).unwrap_err();
+        assert!(e.downcast_ref::<serde_json::Error>().is_some(), "{e:#}");
+    }
+
+    #[test]
+    fn missing_and_corrupt_are_distinguished() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Storage::from(LocalStorage::new(dir.path()).unwrap());
+        assert!(matches!(
+            storage.load::<Legacy>("missing"),
+            Err(LoadError::Missing)
+        ));
+        fs::write(dir.path().join("broken.json"), r#"{"version":1"#).unwrap();
+        assert!(matches!(
+            storage.load::<Legacy>("broken"),
+            Err(LoadError::Corrupt(_))
+        ));
+    }
+}
@@ -0,0 +1,636 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use futures::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serenity::model::Timestamp;
+
+use crate::{
+    cache::TtlCache,
+    common_games::AppId,
+    steam::{AppType, Game},
+    storage::{LoadError, Record, Storage},
+};
+
+/// ストアの情報をキャッシュしておく期間
+const CACHE_TTL: Duration = Duration::from_secs(6 * 60 * 60);
+
+/// アプリの種類をキャッシュしておく期間
+///
+/// 種類はほとんど変わらないので長めにする
+const APP_TYPE_CACHE_TTL: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// ゲームだけを選ぶときに、一度に種類を調べる数
+const APP_TYPE_CHUNK_SIZE: usize = 32;
+
+/// 1 回の呼び出しでストアに種類を問い合わせる数の上限
+///
+/// ストアは 5 分に 200 件ほどしか答えないので、残りは名前から推測する。
+/// 調べた種類は保存しておくので、呼び出すたびに推測するものは減っていく
+const MAX_APP_TYPE_LOOKUPS: usize = 64;
+
+/// まとめて取得するときに同時に送るリクエストの数
+const CONCURRENCY: usize = 8;
+
+/// 価格を取得する地域
+pub const DEFAULT_COUNTRY: &str = "jp";
+
+/// ストアのページから取得したゲームの情報
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AppDetails {
+    pub appid: AppId,
+    pub name: String,
+    /// `game`, `dlc`, `demo`, `music` など
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub header_image: Option<String>,
+    pub capsule_image: Option<String>,
+    pub is_free: bool,
+    pub price: Option<Price>,
+    pub categories: Vec<Category>,
+    pub genres: Vec<String>,
+    /// [`StoreApiClient::get_app_details_with_reviews`] で取得したときだけ入る
+    pub review: Option<ReviewSummary>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Price {
+    pub currency: String,
+    /// 通貨の 1/100 を単位とした価格
+    pub initial: u64,
+    #[serde(rename = "final")]
+    pub final_: u64,
+    pub discount_percent: u64,
+    pub initial_formatted: String,
+    pub final_formatted: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Category {
+    pub id: u64,
+    pub description: String,
+}
+
+/// [appreviews](https://partner.steamgames.com/doc/store/getreviews) の `query_summary`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ReviewSummary {
+    pub review_score_desc: String,
+    pub total_positive: u64,
+    pub total_reviews: u64,
+}
+
+impl Price {
+    /// 割引があれば元の価格と割引率も並べる
+    pub fn text(&self) -> String {
+        if self.discount_percent > 0 {
+            format!(
+                "~~{}~~ **{}** (-{}%)",
+                self.initial_formatted, self.final_formatted, self.discount_percent
+            )
+        } else {
+            self.final_formatted.clone()
+        }
+    }
+
+    /// 通貨の 1/100 を単位とした金額の表示
+    pub fn format_amount(currency: &str, amount: u64) -> String {
+        let (units, cents) = (amount / 100, amount % 100);
+        let mut digits = units.to_string();
+        let mut i = digits.len();
+        while i > 3 {
+            i -= 3;
+            digits.insert(i, ',');
+        }
+        match currency {
+            "JPY" => format!("¥ {digits}"),
+            "USD" => format!("${digits}.{cents:02}"),
+            "EUR" => format!("{digits},{cents:02}€"),
+            _ => format!("{digits}.{cents:02} {currency}"),
+        }
+    }
+}
+
+impl AppDetails {
+    /// マルチプレイに関するカテゴリの表示名
+    pub fn multiplayer_badges(&self) -> Vec<&'static str> {
+        // https://store.steampowered.com/search/ のカテゴリの ID
+        const BADGES: &[(&[u64], &str)] = &[
+            (&[1], "マルチプレイ"),
+            (&[9, 38, 48], "協力プレイ"),
+            (&[49, 36, 47], "PvP"),
+            (&[24, 37, 39], "画面分割"),
+        ];
+        BADGES
+            .iter()
+            .filter(|(ids, _)| self.categories.iter().any(|c| ids.contains(&c.id)))
+            .map(|(_, badge)| *badge)
+            .collect()
+    }
+
+    /// 価格の表示
+    pub fn price_text(&self) -> String {
+        match &self.price {
+            Some(price) => price.text(),
+            None if self.is_free => "無料".to_string(),
+            None => "不明".to_string(),
+        }
+    }
+
+    /// レビューの表示
+    pub fn review_text(&self) -> Option<String> {
+        let review = self.review.as_ref().filter(|r| r.total_reviews > 0)?;
+        Some(format!(
+            "{} ({}% / {}件)",
+            review.review_score_desc,
+            review.total_positive * 100 / review.total_reviews,
+            review.total_reviews
+        ))
+    }
+}
+
+/// 調べたアプリの種類
+///
+/// 再起動しても調べなおさなくて済むように保存しておく
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+struct AppTypes {
+    /// アプリごとの種類と調べた時刻
+    types: HashMap<AppId, (AppType, Timestamp)>,
+}
+
+impl AppTypes {
+    const PERSIST_KEY: &'static str = "app-types";
+
+    /// 期限内に調べた種類
+    fn get(&self, appid: AppId) -> Option<AppType> {
+        let threshold = Timestamp::now().unix_timestamp() - APP_TYPE_CACHE_TTL.as_secs() as i64;
+        self.types
+            .get(&appid)
+            .filter(|(_, checked_at)| checked_at.unix_timestamp() > threshold)
+            .map(|(app_type, _)| *app_type)
+    }
+
+    /// 種類を加えて、期限切れのものを消す
+    fn extend(&mut self, types: impl IntoIterator<Item = (AppId, AppType)>) {
+        let now = Timestamp::now();
+        let threshold = now.unix_timestamp() - APP_TYPE_CACHE_TTL.as_secs() as i64;
+        self.types
+            .retain(|_, (_, checked_at)| checked_at.unix_timestamp() > threshold);
+        self.types.extend(
+            types
+                .into_iter()
+                .map(|(appid, app_type)| (appid, (app_type, now))),
+        );
+    }
+}
+
+impl Record for AppTypes {
+    const VERSION: u32 = 1;
+}
+
+/// Steam のストアの非公式な API のクライアント
+///
+/// 取得した情報はしばらくメモリ上にキャッシュする。アプリの種類は保存もしておく
+#[derive(Clone)]
+pub struct StoreApiClient {
+    /// 価格が地域ごとに異なるので地域と組にしてキャッシュする
+    cache: TtlCache<(AppId, String), Option<AppDetails>>,
+    /// アプリごとの種類
+    app_types: Arc<Mutex<AppTypes>>,
+    /// 検索した語ごとの結果
+    search_cache: TtlCache<String, Vec<Game>>,
+    /// アプリごとのレビューの集計
+    review_cache: TtlCache<AppId, Option<ReviewSummary>>,
+    storage: Storage,
+}
+
+impl StoreApiClient {
+    /// 保存してあるアプリの種類を読み込んで作る
+    pub fn new(storage: Storage) -> StoreApiClient {
+        let app_types = match storage.load::<AppTypes>(AppTypes::PERSIST_KEY) {
+            Ok(app_types) => app_types,
+            Err(LoadError::Missing) => AppTypes::default(),
+            Err(e) => {
+                tracing::warn!("{e:?}");
+                AppTypes::default()
+            }
+        };
+        StoreApiClient {
+            cache: TtlCache::new(CACHE_TTL),
+            app_types: Arc::new(Mutex::new(app_types)),
+            search_cache: TtlCache::new(CACHE_TTL),
+            review_cache: TtlCache::new(CACHE_TTL),
+            storage,
+        }
+    }
+
+    /// ストアでアプリを名前で検索する
+    ///
+    /// 日本語の名前があれば日本語の名前を返すので、日本語の名前で探すときに使う
+    pub async fn search_apps(&self, term: &str) -> Result<Vec<Game>> {
+        if let Some(apps) = self.search_cache.get(&term.to_string()) {
+            return Ok(apps);
+        }
+
+        #[derive(Deserialize, Debug)]
+        struct Response {
+            #[serde(default)]
+            items: Vec<Item>,
+        }
+
+        #[derive(Deserialize, Debug)]
+        struct Item {
+            id: AppId,
+            name: String,
+            #[serde(rename = "type")]
+            kind: String,
+        }
+
+        let Response { items } = reqwest::Client::default()
+            .get("https://store.steampowered.com/api/storesearch/")
+            .query(&[("term", term), ("l", "japanese"), ("cc", DEFAULT_COUNTRY)])
+            .send()
+            .await
+            .context("request failed")?
+            .json()
+            .await
+            .context("invalid json")?;
+        let apps = items
+            .into_iter()
+            .filter(|item| item.kind == "app")
+            .map(|item| Game {
+                appid: item.id,
+                name: item.name,
+            })
+            .collect::<Vec<_>>();
+        self.search_cache.insert(term.to_string(), apps.clone());
+        Ok(apps)
+    }
+
+    /// `games` のうちゲームであるものを、順番を保ったまま `limit` 件まで返す
+    ///
+    /// 種類を調べるリクエストを減らすため、先頭から少しずつ調べて `limit` 件集まったらやめる。
+    /// ストアに問い合わせるのは [`MAX_APP_TYPE_LOOKUPS`] 件までで、残りは名前から推測する
+    pub async fn filter_games(&self, games: Vec<Game>, limit: usize) -> Vec<Game> {
+        let mut filtered = Vec::new();
+        let mut lookups = MAX_APP_TYPE_LOOKUPS;
+        for chunk in games.chunks(APP_TYPE_CHUNK_SIZE) {
+            if filtered.len() >= limit {
+                break;
+            }
+            let types = self.get_app_types_within(chunk, &mut lookups).await;
+            filtered.extend(
+                chunk
+                    .iter()
+                    .filter(|game| types.get(&game.appid) == Some(&AppType::Game))
+                    .take(limit - filtered.len())
+                    .cloned(),
+            );
+        }
+        filtered
+    }
+
+    /// 複数のアプリの種類をまとめて調べる
+    ///
+    /// ストアに問い合わせるのは [`MAX_APP_TYPE_LOOKUPS`] 件までで、残りは名前から推測する
+    pub async fn get_app_types(&self, games: &[Game]) -> HashMap<AppId, AppType> {
+        self.get_app_types_within(games, &mut MAX_APP_TYPE_LOOKUPS.clone())
+            .await
+    }
+
+    /// 保存していない種類は `lookups` 件までストアに問い合わせ、問い合わせた数だけ `lookups` を減らす
+    async fn get_app_types_within(
+        &self,
+        games: &[Game],
+        lookups: &mut usize,
+    ) -> HashMap<AppId, AppType> {
+        let mut types = HashMap::new();
+        let mut unknown = Vec::new();
+        {
+            let app_types = self.app_types.lock().unwrap();
+            for game in games {
+                match app_types.get(game.appid) {
+                    Some(app_type) => {
+                        types.insert(game.appid, app_type);
+                    }
+                    None => unknown.push(game.clone()),
+                }
+            }
+        }
+
+        unknown.truncate(*lookups);
+        *lookups -= unknown.len();
+        let fetched = stream::iter(unknown)
+            .map(|game| async move { (game.appid, self.fetch_app_type(&game).await) })
+            .buffer_unordered(CONCURRENCY)
+            .collect::<Vec<_>>()
+            .await;
+        let mut checked = Vec::new();
+        for (appid, app_type) in fetched {
+            if let Some(app_type) = app_type {
+                checked.push((appid, app_type));
+                types.insert(appid, app_type);
+            }
+        }
+        // 問い合わせなかったものと取得できなかったものは推測する
+        for game in games {
+            types
+                .entry(game.appid)
+                .or_insert_with(|| game.guess_app_type());
+        }
+
+        self.remember_app_types(checked);
+        types
+    }
+
+    /// 保存している種類があれば返す
+    ///
+    /// ストアには問い合わせない
+    pub fn known_app_type(&self, appid: AppId) -> Option<AppType> {
+        self.app_types.lock().unwrap().get(appid)
+    }
+
+    /// 調べた種類を覚えて保存する
+    fn remember_app_types(&self, checked: Vec<(AppId, AppType)>) {
+        if checked.is_empty() {
+            return;
+        }
+        let app_types = {
+            let mut app_types = self.app_types.lock().unwrap();
+            app_types.extend(checked);
+            app_types.clone()
+        };
+        if let Err(e) = self.storage.save(AppTypes::PERSIST_KEY, &app_types) {
+            tracing::warn!("{e:?}");
+        }
+    }
+
+    /// アプリの種類をストアの情報から調べる
+    ///
+    /// ストアのページがないものは名前から推測する。取得できなかったときは `None` になる
+    async fn fetch_app_type(&self, game: &Game) -> Option<AppType> {
+        match self.fetch_store_type(game.appid).await {
+            Ok(kind) => Some(kind.map_or_else(
+                || game.guess_app_type(),
+                |kind| AppType::from_store_type(&kind),
+            )),
+            // 一時的なエラーかもしれないので覚えずに、次は取得しなおす
+            Err(e) => {
+                tracing::warn!("{e:?}");
+                None
+            }
+        }
+    }
+
+    /// appdetails の `type` だけを取得する
+    ///
+    /// ストアのページがないアプリは `None` になる
+    async fn fetch_store_type(&self, appid: AppId) -> Result<Option<String>> {
+        // 詳細を取得したことがあれば、そこから種類がわかる
+        if let Some(details) = self.cache.get(&(appid, DEFAULT_COUNTRY.to_string())) {
+            return Ok(details.map(|details| details.kind));
+        }
+
+        #[derive(Deserialize, Debug)]
+        struct Response {
+            success: bool,
+            data: Option<Data>,
+        }
+
+        #[derive(Deserialize, Debug)]
+        struct Data {
+            #[serde(rename = "type")]
+            kind: String,
+        }
+
+        let appid_ = appid.to_string();
+        let mut resp: HashMap<String, Response> = reqwest::Client::default()
+            .get("https://store.steampowered.com/api/appdetails")
+            .query(&[("appids", appid_.as_str()), ("filters", "basic")])
+            .send()
+            .await
+            .context("request failed")?
+            .json()
+            .await
+            .context("invalid json")?;
+        Ok(match resp.remove(&appid_) {
+            Some(Response {
+                success: true,
+                data: Some(data),
+            }) => Some(data.kind),
+            _ => None,
+        })
+    }
+
+    /// 複数のゲームの情報をまとめて取得する
+    ///
+    /// 取得できなかったゲームは含まれない
+    pub async fn get_app_details_many(
+        &self,
+        appids: impl IntoIterator<Item = AppId>,
+    ) -> HashMap<AppId, AppDetails> {
+        self.get_app_details_many_in(appids, DEFAULT_COUNTRY).await
+    }
+
+    /// 複数のゲームの情報を `country` の価格でまとめて取得する
+    pub async fn get_app_details_many_in(
+        &self,
+        appids: impl IntoIterator<Item = AppId>,
+        country: &str,
+    ) -> HashMap<AppId, AppDetails> {
+        let details = stream::iter(appids)
+            .map(|appid| self.get_app_details_in(appid, country))
+            .buffer_unordered(CONCURRENCY)
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .filter_map(|details| match details {
+                Ok(details) => details,
+                Err(e) => {
+                    tracing::warn!("{e:?}");
+                    None
+                }
+            })
+            .map(|details| (details.appid, details))
+            .collect::<HashMap<_, _>>();
+        // 詳細から種類もわかるので、あとで種類を調べなくて済むように覚えておく
+        self.remember_app_types(
+            details
+                .values()
+                .map(|details| (details.appid, AppType::from_store_type(&details.kind)))
+                .collect(),
+        );
+        details
+    }
+
+    /// 複数のゲームの情報をまとめて取得する
+    ///
+    /// キャッシュしていないものは `lookups` 件までしか問い合わせない。
+    /// 問い合わせなかったゲームは、取得した情報と別に返す
+    pub async fn get_app_details_within(
+        &self,
+        appids: impl IntoIterator<Item = AppId>,
+        lookups: usize,
+    ) -> (HashMap<AppId, AppDetails>, Vec<AppId>) {
+        let (cached, mut uncached): (Vec<_>, Vec<_>) = appids.into_iter().partition(|appid| {
+            self.cache
+                .get(&(*appid, DEFAULT_COUNTRY.to_string()))
+                .is_some()
+        });
+        let skipped = uncached.split_off(lookups.min(uncached.len()));
+        let details = self
+            .get_app_details_many(cached.into_iter().chain(uncached))
+            .await;
+        (details, skipped)
+    }
+
+    /// 複数のゲームの情報をレビューと一緒にまとめて取得する
+    ///
+    /// レビューは 1 件ごとに別に問い合わせるので、表示するときだけ使う
+    pub async fn get_app_details_with_reviews(
+        &self,
+        appids: impl IntoIterator<Item = AppId>,
+    ) -> HashMap<AppId, AppDetails> {
+        let mut details = self.get_app_details_many(appids).await;
+        let reviews = stream::iter(details.keys().copied().collect::<Vec<_>>())
+            .map(|appid| async move { (appid, self.get_review(appid).await) })
+            .buffer_unordered(CONCURRENCY)
+            .collect::<Vec<_>>()
+            .await;
+        for (appid, review) in reviews {
+            if let Some(details) = details.get_mut(&appid) {
+                details.review = review;
+            }
+        }
+        details
+    }
+
+    /// レビューの集計を取得する
+    ///
+    /// 取れなくても表示しないだけなので、エラーは `None` にする
+    async fn get_review(&self, appid: AppId) -> Option<ReviewSummary> {
+        if let Some(review) = self.review_cache.get(&appid) {
+            return review;
+        }
+
+        #[derive(Deserialize, Debug)]
+        struct ReviewsResponse {
+            query_summary: ReviewSummary,
+        }
+
+        let review = reqwest::Client::default()
+            .get(format!("https://store.steampowered.com/appreviews/{appid}"))
+            .query(&[
+                ("json", "1"),
+                ("num_per_page", "0"),
+                ("language", "all"),
+                ("purchase_type", "all"),
+            ])
+            .send()
+            .await
+            .ok()?
+            .json::<ReviewsResponse>()
+            .await
+            .ok()
+            .map(|r| r.query_summary);
+        // 一時的なエラーかもしれないので、取れたときだけ覚えておく
+        if review.is_some() {
+            self.review_cache.insert(appid, review.clone());
+        }
+        review
+    }
+
+    /// ゲームの情報を取得する
+    ///
+    /// ストアのページがないゲームは `None` になる
+    pub async fn get_app_details(&self, appid: AppId) -> Result<Option<AppDetails>> {
+        self.get_app_details_in(appid, DEFAULT_COUNTRY).await
+    }
+
+    /// ゲームの情報を `country` の価格で取得する
+    ///
+    /// `country` は ISO 3166 の 2 文字の国コード
+    pub async fn get_app_details_in(
+        &self,
+        appid: AppId,
+        country: &str,
+    ) -> Result<Option<AppDetails>> {
+        let key = (appid, country.to_lowercase());
+        if let Some(details) = self.cache.get(&key) {
+            return Ok(details);
+        }
+
+        let details = self.fetch_app_details(appid, &key.1).await?;
+        self.cache.insert(key, details.clone());
+        Ok(details)
+    }
+
+    async fn fetch_app_details(&self, appid: AppId, country: &str) -> Result<Option<AppDetails>> {
+        #[derive(Deserialize, Debug)]
+        struct Response {
+            success: bool,
+            data: Option<Data>,
+        }
+
+        #[derive(Deserialize, Debug)]
+        struct Data {
+            name: String,
+            #[serde(rename = "type")]
+            kind: String,
+            header_image: Option<String>,
+            capsule_image: Option<String>,
+            #[serde(default)]
+            is_free: bool,
+            price_overview: Option<Price>,
+            #[serde(default)]
+            categories: Vec<Category>,
+            #[serde(default)]
+            genres: Vec<Genre>,
+        }
+
+        #[derive(Deserialize, Debug)]
+        struct Genre {
+            description: String,
+        }
+
+        let appid_ = appid.to_string();
+        let mut resp: HashMap<String, Response> = reqwest::Client::default()
+            .get("https://store.steampowered.com/api/appdetails")
+            .query(&[
+                ("appids", appid_.as_str()),
+                ("cc", country),
+                ("l", "japanese"),
+                // 説明文などの使わない大きな項目を省く
+                ("filters", "basic,price_overview,categories,genres"),
+            ])
+            .send()
+            .await
+            .context("request failed")?
+            .json()
+            .await
+            .context("invalid json")?;
+        let Some(Response {
+            success: true,
+            data: Some(data),
+        }) = resp.remove(&appid_)
+        else {
+            return Ok(None);
+        };
+
+        Ok(Some(AppDetails {
+            appid,
+            name: data.name,
+            kind: data.kind,
+            header_image: data.header_image,
+            capsule_image: data.capsule_image,
+            is_free: data.is_free,
+            price: data.price_overview,
+            categories: data.categories,
+            genres: data.genres.into_iter().map(|g| g.description).collect(),
+            review: None,
+        }))
+    }
+}
@@ -1,30 +1,100 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
 use serde::{Deserialize, Serialize};
-use shuttle_persist::PersistInstance;
+use serde_json::Value;
+
+use crate::storage::{LoadError, Record, Storage};
 
 #[derive(Deserialize, Serialize, PartialEq, Eq, Hash, Debug)]
-pub struct User(String);
+pub struct User {
+    steam_id: String,
+    /// 参加している Steam ファミリーの合言葉
+    ///
+    /// Steam のファミリーは API で取得できないので、ユーザーが申告する
+    #[serde(default)]
+    family: Option<String>,
+    /// 持っているゲームの `/lfg` の募集で呼ばれたい
+    #[serde(default)]
+    lfg_notifications: bool,
+}
 
 impl User {
+    /// 保存するときのキーの接頭辞
+    pub const PERSIST_KEY_PREFIX: &'static str = "discord-user-";
+
     pub fn new(steam_id: String) -> User {
-        User(steam_id)
+        User {
+            steam_id,
+            family: None,
+            lfg_notifications: false,
+        }
     }
 
     pub fn steam_id(&self) -> &str {
-        &self.0
+        &self.steam_id
+    }
+
+    pub fn family(&self) -> Option<&str> {
+        self.family.as_deref()
+    }
+
+    pub fn set_family(&mut self, family: Option<String>) {
+        self.family = family;
+    }
+
+    pub fn lfg_notifications(&self) -> bool {
+        self.lfg_notifications
     }
 
-    pub fn save(&self, discord_id: &str, persist: &PersistInstance) -> Result<()> {
-        persist.save(&Self::generate_persist_key(discord_id), self)?;
+    pub fn set_lfg_notifications(&mut self, enabled: bool) {
+        self.lfg_notifications = enabled;
+    }
+
+    /// 登録しているすべてのユーザーと Discord の ID
+    ///
+    /// 読めなかったユーザーは除く
+    pub fn load_all(storage: &Storage) -> Result<Vec<(String, User)>> {
+        let mut users = Vec::new();
+        for key in storage.keys(Self::PERSIST_KEY_PREFIX)? {
+            let discord_id = key[Self::PERSIST_KEY_PREFIX.len()..].to_string();
+            match Self::load(&discord_id, storage) {
+                Ok(user) => users.push((discord_id, user)),
+                Err(e) => tracing::warn!("{e:?}"),
+            }
+        }
+        Ok(users)
+    }
+
+    pub fn save(&self, discord_id: &str, storage: &Storage) -> Result<()> {
+        storage.save(&Self::generate_persist_key(discord_id), self)?;
         Ok(())
     }
 
-    pub fn load(discord_id: &str, persist: &PersistInstance) -> Result<User> {
-        let user = persist.load(&Self::generate_persist_key(discord_id))?;
-        Ok(user)
+    pub fn load(discord_id: &str, storage: &Storage) -> Result<User, LoadError> {
+        storage.load(&Self::generate_persist_key(discord_id))
     }
 
     fn generate_persist_key(discord_id: &str) -> String {
-        format!("discord-user-{discord_id}")
+        format!("{}{discord_id}", Self::PERSIST_KEY_PREFIX)
+    }
+}
+
+impl Record for User {
+    const VERSION: u32 = 2;
+
+    fn upgrade(version: u32, data: Value) -> Result<Self> {
+        match (version, data) {
+            // バージョンをつける前は `User(String)` をそのまま保存していた
+            // shuttle-persist からは Steam ID の文字列、JSON からは文字列のリテラルとして読める
+            (0, Value::String(text)) => {
+                let steam_id = serde_json::from_str::<String>(&text).unwrap_or(text);
+                if steam_id.is_empty() || !steam_id.chars().all(|c| c.is_ascii_digit()) {
+                    bail!("invalid legacy steam id");
+                }
+                Ok(User::new(steam_id))
+            }
+            // バージョン 1 は `User(String)`
+            (1, data) => Ok(User::new(serde_json::from_value(data)?)),
+            (version, _) => bail!("unsupported schema version {version}"),
+        }
     }
 }